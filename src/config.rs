@@ -0,0 +1,104 @@
+use yaml_rust::{Yaml, YamlLoader};
+
+use crate::value::{PinError, Temperature};
+
+#[derive(new, Debug, PartialEq, Clone)]
+pub struct PinConfig
+{
+    pub pin: u8,
+    pub kind: String,
+    #[new(default)]
+    pub name: Option<String>,
+    #[new(default)]
+    pub low: Option<Temperature>,
+    #[new(default)]
+    pub high: Option<Temperature>
+}
+
+impl PinConfig
+{
+    pub fn from_yaml(yaml: &Yaml) -> Result<PinConfig, PinError>
+    {
+        let pin = yaml["pin"].as_i64().ok_or(PinError::InvalidValue("Expected pin number"))?;
+        if !(0..=i64::from(u8::MAX)).contains(&pin) {
+            return Err(PinError::OutOfRange(format!("pin {} not within 0..={}", pin, u8::MAX)));
+        }
+        let kind = yaml["kind"].as_str().ok_or(PinError::InvalidValue("Expected pin kind"))?;
+        if !["digital", "analog", "temperature", "voltage"].contains(&kind) {
+            return Err(PinError::UnknownKind(kind.to_string()));
+        }
+        let name = match &yaml["name"] {
+            Yaml::BadValue | Yaml::Null => None,
+            v => Some(v.as_str().ok_or(PinError::InvalidValue("Expected name string"))?.to_string())
+        };
+        let threshold = |key: &str| match &yaml[key] {
+            Yaml::BadValue | Yaml::Null => Ok(None),
+            v => Temperature::from_yaml(v).map(Some).ok_or(PinError::InvalidValue("Unable to parse threshold"))
+        };
+        Ok(PinConfig { pin: pin as u8, kind: kind.to_string(), name, low: threshold("low")?, high: threshold("high")? })
+    }
+}
+
+/**
+ * - pin: 3
+ *   kind: temperature
+ *   name: living_room
+ *   low: 18
+ *   high: 24.5
+ */
+pub fn load_pins(yaml: &str) -> Result<Vec<PinConfig>, PinError>
+{
+    let docs = YamlLoader::load_from_str(yaml).map_err(|_| PinError::InvalidValue("Unable to parse yaml"))?;
+    let pins = match docs.first() {
+        Some(Yaml::Array(pins)) => pins,
+        None | Some(Yaml::Null) => return Ok(Vec::new()),
+        _ => return Err(PinError::InvalidValue("Expected a list of pins"))
+    };
+    pins.iter().map(PinConfig::from_yaml).collect()
+}
+
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests
+{
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+
+    #[test]
+    fn test_load_pins()
+    {
+        let pins = load_pins("
+- pin: 3
+  kind: temperature
+  name: living_room
+  low: 18
+  high: 24.5
+- pin: 5
+  kind: digital
+  name: living_room_heater
+- pin: 14
+  kind: analog
+").unwrap();
+        assert_eq!(pins.len(), 3);
+        assert_eq!(pins[0], PinConfig { pin: 3, kind: "temperature".to_string(), name: Some("living_room".to_string()),
+            low: Some(Temperature::new(18_f32)), high: Some(Temperature::new(24.5_f32)) });
+        assert_eq!(pins[1].name, Some("living_room_heater".to_string()));
+        assert_eq!(pins[1].low, None);
+        assert_eq!(pins[2], PinConfig::new(14, "analog".to_string()));
+
+        assert_eq!(load_pins("").unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn test_load_pins_malformed()
+    {
+        assert_eq!(load_pins("pin: 3").unwrap_err(), PinError::InvalidValue("Expected a list of pins"));
+        assert_eq!(load_pins("- kind: digital").unwrap_err(), PinError::InvalidValue("Expected pin number"));
+        assert_eq!(load_pins("- pin: 3").unwrap_err(), PinError::InvalidValue("Expected pin kind"));
+        assert_eq!(load_pins("- pin: 300\n  kind: digital").unwrap_err(), PinError::OutOfRange("pin 300 not within 0..=255".to_string()));
+        assert_eq!(load_pins("- pin: 3\n  kind: pressure").unwrap_err(), PinError::UnknownKind("pressure".to_string()));
+        assert_eq!(load_pins("- pin: 3\n  kind: temperature\n  low: cold").unwrap_err(), PinError::InvalidValue("Unable to parse threshold"));
+        assert_eq!(load_pins("- pin: 3\n  kind: [").unwrap_err(), PinError::InvalidValue("Unable to parse yaml"));
+    }
+}
@@ -1,17 +1,88 @@
-use chrono::{Local, DateTime};
-use std::ops::Add;
+#[cfg(feature = "std")]
+use chrono::{Local, DateTime, Duration};
+use core::cmp::Ordering;
+use core::ops::Add;
 use num::{ToPrimitive, Zero};
 
+use crate::value::Temperature;
+
 pub fn average<T>(numbers: &[T]) -> f32
     where T: Add<T, Output=T> + Copy + Zero + ToPrimitive
 {
-    if numbers.len() > 0 {
+    if !numbers.is_empty() {
         return numbers.iter().fold(T::zero(), |sum, &value| sum + value).to_f32()
             .map(|n| n / numbers.len() as f32 ).unwrap_or(0_f32);
     }
     0_f32
 }
 
+/**
+ * two (raw, temperature) reference points, readings are interpolated/extrapolated linearly
+ */
+#[derive(new, Debug, PartialEq, Clone)]
+pub struct Calibration
+{
+    pub first: (u16, f32),
+    pub second: (u16, f32)
+}
+
+pub fn analog_to_temperature(raw: u16, cal: &Calibration) -> Temperature
+{
+    let (raw_a, temp_a) = cal.first;
+    let (raw_b, temp_b) = cal.second;
+    if raw_a == raw_b {
+        return Temperature::new(temp_a);
+    }
+    let slope = (temp_b - temp_a) / (raw_b as f32 - raw_a as f32);
+    Temperature::new(temp_a + (raw as f32 - raw_a as f32) * slope)
+}
+
+pub fn median(numbers: &[f32]) -> f32
+{
+    if numbers.is_empty() {
+        return 0_f32;
+    }
+    let mut sorted = numbers.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    let middle = sorted.len() / 2;
+    if middle * 2 == sorted.len() { (sorted[middle - 1] + sorted[middle]) / 2_f32 } else { sorted[middle] }
+}
+
+/**
+ * linear interpolation between the closest ranks, p is clamped to 0..=100 and NaN counts as 0
+ */
+pub fn percentile(numbers: &[f32], p: f32) -> f32
+{
+    if numbers.is_empty() {
+        return 0_f32;
+    }
+    let mut sorted = numbers.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    let p = if p.is_nan() { 0_f32 } else { p.clamp(0_f32, 100_f32) };
+    let rank = p / 100_f32 * (sorted.len() - 1) as f32;
+    let lower = rank as usize;
+    let upper = (lower + 1).min(sorted.len() - 1);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f32)
+}
+
+/**
+ * f32::abs is only in core since rust 1.84, clearing the sign bit works without std
+ */
+pub fn abs_f32(value: f32) -> f32
+{
+    f32::from_bits(value.to_bits() & 0x7fff_ffff)
+}
+
+pub fn combine_averages(avg_a: f32, count_a: usize, avg_b: f32, count_b: usize) -> f32
+{
+    let count = count_a + count_b;
+    if count == 0 {
+        return 0_f32;
+    }
+    (avg_a * count_a as f32 + avg_b * count_b as f32) / count as f32
+}
+
+#[cfg(feature = "std")]
 pub fn more_recent_date(dt1: Option<DateTime<Local>>, dt2: Option<DateTime<Local>>) -> Option<DateTime<Local>>
 {
     if let Some(d1) = dt1 {
@@ -24,17 +95,63 @@ pub fn more_recent_date(dt1: Option<DateTime<Local>>, dt2: Option<DateTime<Local
     dt2
 }
 
+/**
+ * 1h 5m 3s, zero components are left out, below one second is 0s
+ */
+#[cfg(feature = "std")]
+pub fn format_duration(d: Duration) -> String
+{
+    let total = d.num_seconds();
+    let seconds = total.unsigned_abs();
+    let parts: Vec<String> = [(seconds / 3600, "h"), (seconds / 60 % 60, "m"), (seconds % 60, "s")].iter()
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, unit)| format!("{}{}", value, unit))
+        .collect();
+    if parts.is_empty() {
+        return "0s".to_string();
+    }
+    format!("{}{}", if total < 0 { "-" } else { "" }, parts.join(" "))
+}
+
 pub fn percent_to_analog(num: u8) -> u16
 {
     if num >= 100 { 1023_u16 } else { (num as u32 * 1023_u32 / 100) as u16}
 }
 
+/**
+ * (num/100)^gamma scaled to 1023, gamma 2.2 dims uniformly to the eye
+ */
+#[cfg(feature = "std")]
+pub fn percent_to_analog_gamma(num: u8, gamma: f32) -> u16
+{
+    match num {
+        0 => 0_u16,
+        n if n >= 100 => 1023_u16,
+        n => ((n as f32 / 100_f32).powf(gamma) * 1023_f32) as u16
+    }
+}
+
+/**
+ * for reverse wired sensors, raw above max gives 0
+ */
+pub fn invert_analog(raw: u16, max: u16) -> u16
+{
+    max.saturating_sub(raw)
+}
+
+pub fn analog_to_percent(num: u16) -> u8
+{
+    if num >= 1023 { 100_u8 } else { ((num as u32 * 100_u32 + 511_u32) / 1023_u32) as u8}
+}
+
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests
 {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
     use super::*;
+    #[cfg(feature = "std")]
     use chrono::Duration;
 
     #[test]
@@ -46,6 +163,57 @@ mod tests
         assert_eq!(percent_to_analog(50), 511);
     }
 
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_percent_to_analog_gamma()
+    {
+        assert_eq!(percent_to_analog_gamma(0, 2.2_f32), 0);
+        assert_eq!(percent_to_analog_gamma(100, 2.2_f32), 1023);
+        assert_eq!(percent_to_analog_gamma(150, 2.2_f32), 1023);
+        assert_eq!(percent_to_analog_gamma(50, 2_f32), 255);
+        assert_eq!(percent_to_analog_gamma(50, 1_f32), percent_to_analog(50));
+        for gamma in &[1_f32, 2_f32, 2.2_f32] {
+            for num in 0..100_u8 {
+                assert_eq!(percent_to_analog_gamma(num + 1, *gamma) >= percent_to_analog_gamma(num, *gamma), true);
+            }
+        }
+    }
+
+    #[test]
+    fn test_invert_analog()
+    {
+        assert_eq!(invert_analog(0, 1023), 1023);
+        assert_eq!(invert_analog(1023, 1023), 0);
+        assert_eq!(invert_analog(2000, 1023), 0);
+        assert_eq!(invert_analog(511, 1023), 512);
+        assert_eq!(invert_analog(100, 255), 155);
+    }
+
+    #[test]
+    fn test_analog_to_percent()
+    {
+        assert_eq!(analog_to_percent(1023), 100);
+        assert_eq!(analog_to_percent(4000), 100);
+        assert_eq!(analog_to_percent(0), 0);
+        assert_eq!(analog_to_percent(511), 50);
+        assert_eq!(analog_to_percent(percent_to_analog(33)), 33);
+    }
+
+    #[test]
+    fn test_analog_to_temperature()
+    {
+        let cal = Calibration::new((200, 0_f32), (600, 100_f32));
+        assert_eq!(analog_to_temperature(200, &cal), Temperature::new(0_f32));
+        assert_eq!(analog_to_temperature(600, &cal), Temperature::new(100_f32));
+        assert_eq!(analog_to_temperature(400, &cal), Temperature::new(50_f32));
+        assert_eq!(analog_to_temperature(100, &cal), Temperature::new(-25_f32));
+        assert_eq!(analog_to_temperature(1000, &cal), Temperature::new(200_f32));
+
+        let inverted = Calibration::new((800, -10_f32), (300, 40_f32));
+        assert_eq!(analog_to_temperature(550, &inverted), Temperature::new(15_f32));
+        assert_eq!(analog_to_temperature(550, &Calibration::new((300, 20_f32), (300, 30_f32))), Temperature::new(20_f32));
+    }
+
     #[test]
     fn test_average()
     {
@@ -56,7 +224,63 @@ mod tests
         assert_eq!(average(&[3.35_f32, 1.45_f32]), 2.4_f32);
     }
 
+    #[test]
+    fn test_median()
+    {
+        assert_eq!(median(&[3_f32, 1_f32, 2_f32]), 2_f32);
+        assert_eq!(median(&[4_f32, 1_f32, 3_f32, 2_f32]), 2.5_f32);
+        assert_eq!(median(&[-1_f32]), -1_f32);
+        assert_eq!(median(&[]), 0_f32);
+    }
+
+    #[test]
+    fn test_percentile()
+    {
+        let numbers = [15_f32, 20_f32, 35_f32, 40_f32, 50_f32];
+        assert_eq!(percentile(&numbers, 50_f32), 35_f32);
+        assert_eq!(percentile(&numbers, 0_f32), 15_f32);
+        assert_eq!(percentile(&numbers, 100_f32), 50_f32);
+        assert_eq!(percentile(&numbers, 40_f32), 29_f32);
+        assert_eq!(percentile(&numbers, 150_f32), 50_f32);
+        assert_eq!(percentile(&numbers, -10_f32), 15_f32);
+        assert_eq!(percentile(&[4_f32, 1_f32, 3_f32, 2_f32], 50_f32), median(&[4_f32, 1_f32, 3_f32, 2_f32]));
+        assert_eq!(percentile(&[], 50_f32), 0_f32);
+    }
+
+    #[test]
+    fn test_abs_f32()
+    {
+        assert_eq!(abs_f32(-2.5_f32), 2.5_f32);
+        assert_eq!(abs_f32(2.5_f32), 2.5_f32);
+        assert_eq!(abs_f32(-0_f32).to_bits(), 0_f32.to_bits());
+        assert_eq!(abs_f32(f32::NEG_INFINITY), f32::INFINITY);
+        assert_eq!(abs_f32(-f32::NAN).is_nan(), true);
+    }
+
+    #[test]
+    fn test_combine_averages()
+    {
+        assert_eq!(combine_averages(15_f32, 2, 19_f32, 4), 17_f32 + 2_f32 / 3_f32);
+        assert_eq!(combine_averages(10_f32, 1, 20_f32, 1), 15_f32);
+        assert_eq!(combine_averages(10_f32, 3, 0_f32, 0), 10_f32);
+        assert_eq!(combine_averages(0_f32, 0, 0_f32, 0), 0_f32);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_format_duration()
+    {
+        assert_eq!(format_duration(Duration::zero()), "0s");
+        assert_eq!(format_duration(Duration::milliseconds(400)), "0s");
+        assert_eq!(format_duration(Duration::seconds(42)), "42s");
+        assert_eq!(format_duration(Duration::hours(1)), "1h");
+        assert_eq!(format_duration(Duration::seconds(3903)), "1h 5m 3s");
+        assert_eq!(format_duration(Duration::seconds(3603)), "1h 3s");
+        assert_eq!(format_duration(Duration::hours(30)), "30h");
+        assert_eq!(format_duration(Duration::seconds(-90)), "-1m 30s");
+    }
 
+    #[cfg(feature = "std")]
     #[test]
     fn test_more_recent_date()
     {
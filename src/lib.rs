@@ -1,6 +1,12 @@
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+
+extern crate alloc;
 #[macro_use]
 extern crate derive_new;
 
+pub mod value;
+#[cfg(feature = "std")]
 pub mod pin;
+#[cfg(feature = "std")]
+pub mod config;
 pub mod helper;
-
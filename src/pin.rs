@@ -1,300 +1,4106 @@
-use chrono::{Local, DateTime};
+use chrono::{Local, Utc, DateTime, Duration, TimeZone};
 use mosquitto_client::{MosqMessage};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use arraydeque::{ArrayDeque, Wrapping};
-use std::ops::Sub;
-use yaml_rust::{Yaml};
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+use std::marker::PhantomData;
 
-use crate::helper::average;
+use crate::helper::{average, median, percentile};
+pub use crate::value::{MAX_ANALOG, PinCommand, PinError, PinValue, PinValueKey, Temperature, TemperatureRange};
 
-#[derive(new, Default, Debug, PartialEq, PartialOrd, Clone)]
-pub struct Temperature
+pub type ValueParser = Box<dyn Fn(&str) -> Result<PinValue, PinError>>;
+
+/**
+ * custom value kinds (humidity, pressure etc.) parsed before falling back to PinValue::from_string
+ */
+#[derive(Default)]
+pub struct ParserRegistry
+{
+    parsers: HashMap<String, ValueParser>
+}
+
+impl ParserRegistry
+{
+    pub fn register<F>(&mut self, kind: &str, parser: F)
+        where F: Fn(&str) -> Result<PinValue, PinError> + 'static
+    {
+        self.parsers.insert(kind.to_string(), Box::new(parser));
+    }
+
+    pub fn has_parser(&self, kind: &str) -> bool
+    {
+        self.parsers.contains_key(kind)
+    }
+
+    pub fn parse(&self, kind: &str, message: &str) -> Result<PinValue, PinError>
+    {
+        match self.parsers.get(kind) {
+            Some(parser) => parser(message),
+            None => PinValue::from_string(kind, message)
+        }
+    }
+}
+
+/**
+ * time zone the timestamps are kept in, Local by default or Utc where the host time zone is unpredictable,
+ * DateTime<Tz> is not Copy in generic code so timestamps are cloned
+ */
+pub trait Zone: TimeZone + Copy + fmt::Debug
 {
-    pub value: f32
+    fn now() -> DateTime<Self>;
+
+    fn from_timestamp(epoch_secs: i64) -> Option<DateTime<Self>>;
 }
 
-impl Sub for Temperature {
-    type Output = Temperature;
-    fn sub(self, other: Temperature) -> Temperature {
-        Temperature { value: self.value - other.value }
+impl Zone for Local
+{
+    fn now() -> DateTime<Local>
+    {
+        Local::now()
+    }
+
+    fn from_timestamp(epoch_secs: i64) -> Option<DateTime<Local>>
+    {
+        Local.timestamp_opt(epoch_secs, 0).single()
     }
 }
 
-impl Temperature
+impl Zone for Utc
 {
-    pub fn from_str(s: &str) -> Result<Temperature, String>
+    fn now() -> DateTime<Utc>
     {
-        Ok(Temperature::new(s.parse::<f32>().map_err(|_| format!("unable to parse temperature {}", s))?))
+        Utc::now()
     }
 
-    pub fn from_yaml(yaml: &Yaml) -> Option<Temperature>
+    fn from_timestamp(epoch_secs: i64) -> Option<DateTime<Utc>>
     {
-        Some(Temperature { value: yaml.as_f64()? as f32 })
+        Utc.timestamp_opt(epoch_secs, 0).single()
     }
+}
+
+/**
+ * source of the current time, replace SystemClock to guard against clock jumps or to pin time in tests
+ */
+pub trait Clock<Tz: Zone>
+{
+    fn now() -> DateTime<Tz>;
+}
 
-    pub fn abs(&self) -> Temperature
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock;
+
+impl<Tz: Zone> Clock<Tz> for SystemClock
+{
+    fn now() -> DateTime<Tz>
     {
-        Temperature { value: self.value.abs() }
+        Tz::now()
     }
 }
 
+/**
+ * node addressing every node e.g. all/current/digital/5
+ */
+pub const BROADCAST_NODE: &str = "all";
 
+pub type PinState = PinStateIn<Local>;
+pub type UtcPinState = PinStateIn<Utc>;
 
-#[derive(Debug, PartialEq, Clone)]
-pub enum PinValue
+#[derive(new, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize),
+    serde(bound(serialize = "DateTime<Tz>: Serialize", deserialize = "DateTime<Tz>: Deserialize<'de>")))]
+pub struct PinStateIn<Tz: Zone>
+{
+    pub pin: u8,
+    pub value: PinValue,
+    pub dt: DateTime<Tz>,
+    pub until: Option<DateTime<Tz>>
+}
+
+/**
+ * written out because derive(PartialEq) would demand Tz: PartialEq, which chrono's Local does not implement
+ */
+impl<Tz: Zone> PartialEq for PinStateIn<Tz>
 {
-    Temperature(Temperature),
-    Analog(u16),
-    Digital(bool)
+    fn eq(&self, other: &PinStateIn<Tz>) -> bool
+    {
+        self.pin == other.pin && self.value == other.value && self.dt == other.dt && self.until == other.until
+    }
 }
 
-impl PinValue
+impl<Tz: Zone> PinStateIn<Tz>
 {
-    pub fn from_string(kind: &str, message: &str) -> Result<PinValue, &'static str>
+    pub fn now(pin: u8, value: PinValue) -> PinStateIn<Tz>
     {
-        match kind {
-                "digital" => {
-                    let value = message.parse::<u8>().map_err(|_| "Unable to parse digital value")? > 0;
-                    Ok(PinValue::Digital(value))
-                },
-                "analog" => {
-                    let value = message.parse::<u16>().map_err(|_| "Unable to parse analog value")?;
-                    Ok(PinValue::Analog(value))
-                },
-                "temperature" => {
-                    let value = message.parse::<f32>().map_err(|_| "Unable to parse temparature value")?;
-                    Ok(PinValue::Temperature(Temperature {value }))
-                }
-                _ => Err("Unknown pin value type")
-        }
+        PinStateIn { pin, value, dt: Tz::now(), until: None }
     }
 
-    pub fn is_digital(&self) -> bool
+    pub fn with_timeout(mut self, timeout: Duration) -> PinStateIn<Tz>
     {
-        match self { PinValue::Digital(_) => true, _ => false}
+        self.until = Some(Tz::now() + timeout);
+        self
     }
 
-    pub fn is_analog(&self) -> bool
+    pub fn is_on(&self) -> bool
     {
-        match self { PinValue::Analog(_) => true, _ => false}
+        self.value.is_on()
     }
 
-    pub fn is_temperature(&self) -> bool
+    /**
+     * flat columns for export, until as RFC3339
+     */
+    pub fn to_record(&self) -> CsvRecord
+        where Tz::Offset: fmt::Display
     {
-        match self { PinValue::Temperature(_) => true, _ => false}
+        CsvRecord {
+            pin: self.pin,
+            kind: self.value.kind(),
+            value: self.value.as_f32(),
+            raw: self.value.to_payload(),
+            until: self.until.clone().map(|until| until.to_rfc3339())
+        }
     }
 
-    pub fn is_on(&self) -> bool
+    /**
+     * pin and value match, dt and until are ignored
+     */
+    pub fn same_reading(&self, other: &PinStateIn<Tz>) -> bool
     {
-        match self { PinValue::Analog(v) => *v > 0u16, PinValue::Digital(v) => *v == true, _ => false}
+        self.pin == other.pin && self.value == other.value
     }
 
-    pub fn as_u16(&self) -> u16
+    /**
+     * analog and temperature must differ by more than their threshold,
+     * other kinds by any amount, a different kind is always significant
+     */
+    pub fn is_significant_change_from(&self, other: &PinStateIn<Tz>, analog_threshold: u16, temp_threshold: f32) -> bool
     {
-        match self { PinValue::Analog(v) => v.clone(), PinValue::Digital(v) => if *v == true { 1 } else { 0 }, _ => 0}
+        match (&self.value, &other.value) {
+            (PinValue::Analog(a), PinValue::Analog(b)) => a.abs_diff(*b) > analog_threshold,
+            (PinValue::Temperature(a), PinValue::Temperature(b)) => (a.value - b.value).abs() > temp_threshold,
+            (a, b) => a != b
+        }
     }
 }
 
-#[derive(new, Debug, Clone, PartialEq)]
-pub struct PinState
+#[derive(Debug, PartialEq, Clone)]
+pub struct CsvRecord
 {
     pub pin: u8,
-    pub value: PinValue,
-    pub dt: DateTime<Local>,
-    pub until: Option<DateTime<Local>>
+    pub kind: &'static str,
+    pub value: f32,
+    pub raw: String,
+    pub until: Option<String>
 }
 
-impl PinState
+impl CsvRecord
 {
-    pub fn is_on(&self) -> bool
+    /**
+     * column names in field order
+     */
+    pub fn header() -> [&'static str; 5]
     {
-        self.value.is_on()
+        ["pin", "kind", "value", "raw", "until"]
+    }
+}
+
+pub type PinOperation = PinOperationIn<Local>;
+pub type UtcPinOperation = PinOperationIn<Utc>;
+
+#[derive(Debug, Clone)]
+pub struct ParseOptions
+{
+    pub max_timeout: Duration,
+    /** segments allowed in front of the value type e.g. node1/current/sensor/temperature/5 */
+    pub kind_namespaces: Vec<String>,
+    /** longer topics are rejected before they are split */
+    pub max_segments: usize,
+    /** longer topic or combined payloads are rejected before they are parsed */
+    pub max_payload_len: usize,
+    /** longer json bodies are rejected before they are parsed, kept apart as a json body carries the type and timeout too */
+    pub max_json_len: usize
+}
+
+impl Default for ParseOptions
+{
+    fn default() -> ParseOptions
+    {
+        ParseOptions { max_timeout: Duration::days(30), kind_namespaces: vec!["sensor".to_string(), "actuator".to_string()],
+            max_segments: 16, max_payload_len: 64, max_json_len: 1024 }
+    }
+}
+
+impl ParseOptions
+{
+    fn check_topic(&self, topic: &str) -> Result<(), PinError>
+    {
+        if split_topic(topic).nth(self.max_segments).is_some() {
+            return Err(PinError::OutOfRange(format!("topic has more than {} segments", self.max_segments)));
+        }
+        Ok(())
+    }
+
+    fn check_payload(&self, payload: &str) -> Result<(), PinError>
+    {
+        if payload.len() > self.max_payload_len {
+            return Err(PinError::OutOfRange(format!("payload longer than {} bytes", self.max_payload_len)));
+        }
+        Ok(())
+    }
+
+    fn check_json(&self, json: &str) -> Result<(), PinError>
+    {
+        if json.len() > self.max_json_len {
+            return Err(PinError::OutOfRange(format!("json payload longer than {} bytes", self.max_json_len)));
+        }
+        Ok(())
+    }
+
+    fn until<Tz: Zone>(&self, dt: &DateTime<Tz>, timeout: u32) -> Result<DateTime<Tz>, PinError>
+    {
+        let timeout = Duration::seconds(timeout as i64);
+        if timeout > self.max_timeout {
+            return Err(PinError::OutOfRange(format!("timeout {}s above {}s", timeout.num_seconds(), self.max_timeout.num_seconds())));
+        }
+        dt.clone().checked_add_signed(timeout).ok_or(PinError::InvalidValue("Timeout overflows"))
     }
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PinMode
+{
+    Input,
+    Output,
+    InputOutput
+}
+
 #[derive(new, Debug, Clone)]
-pub struct PinOperation
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize),
+    serde(bound(serialize = "DateTime<Tz>: Serialize", deserialize = "DateTime<Tz>: Deserialize<'de>")))]
+pub struct PinOperationIn<Tz: Zone>
 {
-    pub pin_state: PinState,
+    pub pin_state: PinStateIn<Tz>,
     pub node: String,
+    #[new(default)]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub toggle: bool,
+    /** topic as received, empty when not parsed from a message */
+    #[new(default)]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub raw_topic: String,
+    /** payload as received, empty when not parsed from a message */
+    #[new(default)]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub raw_payload: String
+}
+
+impl<Tz: Zone> From<(String, PinStateIn<Tz>)> for PinOperationIn<Tz>
+{
+    fn from((node, pin_state): (String, PinStateIn<Tz>)) -> PinOperationIn<Tz>
+    {
+        PinOperationIn::new(pin_state, node)
+    }
+}
+
+/**
+ * addressing read from a topic (or a json body), the timeout is checked against ParseOptions when the state is built
+ */
+struct TopicParts<'a>
+{
+    node: String,
+    kind: &'a str,
+    pin: u8,
+    timeout: Option<u32>
+}
+
+/**
+ * the part of a topic parse_topic was looking for when it gave up
+ */
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum TopicSegment
+{
+    KindAndPin,
+    Pin,
+    Operation,
+    Timeout,
+    Node
+}
+
+impl fmt::Display for TopicSegment
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self {
+            TopicSegment::KindAndPin => write!(f, "value type and pin"),
+            TopicSegment::Pin => write!(f, "pin number"),
+            TopicSegment::Operation => write!(f, "'current', 'last' or 'timeout'"),
+            TopicSegment::Timeout => write!(f, "timeout seconds"),
+            TopicSegment::Node => write!(f, "a node name")
+        }
+    }
+}
+
+/**
+ * segment is counted from 1 over the non empty segments, found is None when the expected segment is missing
+ * and segment is the one it should come before
+ */
+#[derive(Debug, PartialEq, Clone)]
+struct TopicMismatch<'a>
+{
+    segment: usize,
+    expected: TopicSegment,
+    found: Option<&'a str>,
+    error: PinError
+}
+
+impl fmt::Display for TopicMismatch<'_>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self.found {
+            Some(found) => write!(f, "expected {} at segment {}, found '{}'", self.expected, self.segment, found),
+            None => write!(f, "expected {} before segment {}, found nothing", self.expected, self.segment)
+        }
+    }
+}
+
+impl From<TopicMismatch<'_>> for PinError
+{
+    fn from(mismatch: TopicMismatch<'_>) -> PinError
+    {
+        mismatch.error
+    }
 }
 
-impl PinOperation
+impl<Tz: Zone> PinOperationIn<Tz>
 {
+    pub fn from_state(node: &str, state: PinStateIn<Tz>) -> PinOperationIn<Tz>
+    {
+        PinOperationIn::new(state, node.to_string())
+    }
+
     /**
      * node1/current/analog/3 2342
      * node1/current/digital/5 1
      * node1/current/digital/5 1
      * node1/current/temperature/5 32.23
      * node1/current/timeout/3600/analog/8 2332
+     * node1/timeout/3600/analog/8 2332
+     * node1/current/toggle/5
+     * building/floor2/node1/current/analog/3 2342
+     * node1/current/sensor/temperature/5 32.23
+     * node1/last/analog/3 2342 (last reported value, parsed like current)
      */
-    pub fn from_message(message: &MosqMessage) -> Result<PinOperation, &str>
+    pub fn from_message(message: &MosqMessage) -> Result<PinOperationIn<Tz>, PinError>
     {
-        let mut paths: Vec<&str> = message.topic().split("/").collect();
-        let pin = paths.pop().ok_or("Unable to read string")
-            .and_then(|s: &str| s.parse::<u8>().map_err(|_| "Unable to parse integer"))?;
-        let value = paths.pop().ok_or("Unknown pin").and_then(|s| PinValue::from_string(s, message.text()))?;
-        let op_current = paths.pop().ok_or("Expected current")?;//.map(|s| s == "current").unwrap_or(false);
-        let node = paths.pop().ok_or("Unknown node")?;
+        PinOperationIn::from_parts(message.topic(), message.text())
+    }
 
-        if "current" == op_current {
-           return Ok(PinOperation {pin_state: PinState { pin, value, dt: Local::now(), until: None }, node: node.to_string()});
-        }
+    pub fn from_message_with_clock<C: Clock<Tz>>(message: &MosqMessage) -> Result<PinOperationIn<Tz>, PinError>
+    {
+        PinOperationIn::from_parts_with_clock::<C>(message.topic(), message.text(), &ParserRegistry::default(), &ParseOptions::default())
+    }
 
-        let timeout = op_current.parse::<u32>();
-        let is_time_out = paths.pop().map(|s| s == "timeout");
-        let until = if is_time_out.is_some() && timeout.is_ok() { Some(Local::now() + chrono::Duration::seconds(timeout.unwrap() as i64)) } else { None };
-        let node = if until.is_some() { paths.pop().ok_or("Unknown node after timeout")? } else { node };
-        Ok(PinOperation {pin_state: PinState { pin, value, dt: Local::now(), until }, node: node.to_string()})
+    pub fn from_parts(topic: &str, payload: &str) -> Result<PinOperationIn<Tz>, PinError>
+    {
+        PinOperationIn::from_parts_with_registry(topic, payload, &ParserRegistry::default())
     }
 
-}
+    /**
+     * dt is taken from the epoch stamped by the device instead of the time the message is parsed
+     */
+    pub fn from_message_with_timestamp(message: &MosqMessage, epoch_secs: i64) -> Result<PinOperationIn<Tz>, PinError>
+    {
+        PinOperationIn::from_parts_with_timestamp(message.topic(), message.text(), epoch_secs)
+    }
 
-#[derive(Default, new, Debug)]
-pub struct PinCollection
-{
-    states: ArrayDeque<[PinState; 20], Wrapping>,
-    changed: ArrayDeque<[PinState; 20], Wrapping>
-}
+    pub fn from_parts_with_timestamp(topic: &str, payload: &str, epoch_secs: i64) -> Result<PinOperationIn<Tz>, PinError>
+    {
+        let dt = Tz::from_timestamp(epoch_secs).ok_or(PinError::InvalidValue("Unable to convert timestamp"))?;
+        PinOperationIn::parse_at(topic, payload, &ParserRegistry::default(), &ParseOptions::default(), dt)
+    }
 
-impl PinCollection
-{
-    pub fn default() -> PinCollection
+    pub fn from_parts_with_registry(topic: &str, payload: &str, registry: &ParserRegistry) -> Result<PinOperationIn<Tz>, PinError>
     {
-        PinCollection {states: ArrayDeque::new(), changed: ArrayDeque::new()}
+        PinOperationIn::from_parts_with_options(topic, payload, registry, &ParseOptions::default())
     }
 
-    pub fn from_states(states: &Vec<PinState>) -> PinCollection
+    pub fn from_parts_with_options(topic: &str, payload: &str, registry: &ParserRegistry, options: &ParseOptions) -> Result<PinOperationIn<Tz>, PinError>
     {
-        let mut col = PinCollection::default();
-        for state in states {
-            col.push(state);
-        }
-        col
+        PinOperationIn::from_parts_with_clock::<SystemClock>(topic, payload, registry, options)
     }
 
-    pub fn push(&mut self, state: &PinState)
+    /**
+     * dt and the timeout deadline are taken from C
+     */
+    pub fn from_parts_with_clock<C: Clock<Tz>>(topic: &str, payload: &str, registry: &ParserRegistry, options: &ParseOptions) -> Result<PinOperationIn<Tz>, PinError>
     {
-        if let PinValue::Digital(v) = state.value {
-            let last_state = self.changed.iter().filter(|s| s.value.is_digital()).next();
-            if let Some(s) = last_state {
-                if let PinValue::Digital(c) = s.value {
-                    if  v != c {
-                        self.changed.push_front(state.clone());
-                    }
-                }
-            } else {
-                self.changed.push_front(state.clone());
-            }
-        } else if let PinValue::Analog(v) = state.value {
-            let last_state = self.changed.iter().filter(|s| s.value.is_analog()).next();
-            if let Some(s) = last_state {
-                if let PinValue::Analog(c) = s.value {
-                    if  (c == 0 && v > 0) || (c > 0 && v == 0) {
-                        self.changed.push_front(state.clone());
-                    }
-                }
-            } else {
-                self.changed.push_front(state.clone());
-            }
-        }
-        self.states.push_front(state.clone());
+        PinOperationIn::parse_at(topic, payload, registry, options, C::now())
     }
 
-    pub fn get_average_temperature(&self, since: &DateTime<Local>) -> Option<Temperature>
+    fn parse_at(topic: &str, payload: &str, registry: &ParserRegistry, options: &ParseOptions, dt: DateTime<Tz>) -> Result<PinOperationIn<Tz>, PinError>
     {
-        let vec: Vec<f32> = self.states.iter()
-            .filter(|state| state.dt > *since )
-            .filter_map(|state|
-                if let PinValue::Temperature(v) = state.value.clone() { Some(v.value) } else { None }
-            )
-            .collect();
-        if vec.len() > 0 {
-            return Some(Temperature::new(average(&vec)));
-        }
-        None
+        options.check_topic(topic)?;
+        options.check_payload(payload)?;
+        let parts = PinOperationIn::<Tz>::parse_topic(topic, options)?;
+        PinOperationIn::operation_at(parts, payload, registry, options, dt, topic, payload)
     }
 
-    pub fn is_on(&self) -> bool
+    /**
+     * shared by the topic and json formats so both resolve kinds through the registry and enforce the same limits
+     */
+    fn operation_at(parts: TopicParts, message: &str, registry: &ParserRegistry, options: &ParseOptions, dt: DateTime<Tz>,
+        raw_topic: &str, raw_payload: &str) -> Result<PinOperationIn<Tz>, PinError>
     {
-        self.changed.front().map(|state| state.until.map(|dt| dt > Local::now()).unwrap_or(true) && match state.value { PinValue::Digital(v) => v, PinValue::Analog(v) => v > 0, _ => false}).unwrap_or(false)
+        let until = match parts.timeout {
+            Some(timeout) => Some(options.until(&dt, timeout)?),
+            None => None
+        };
+        let toggle = parts.kind == "toggle";
+        let value = if toggle { PinValue::Digital(true) } else { registry.parse(parts.kind, message)? };
+        Ok(PinOperationIn {pin_state: PinStateIn { pin: parts.pin, value, dt, until }, node: parts.node, toggle,
+            raw_topic: raw_topic.to_string(), raw_payload: raw_payload.to_string()})
     }
 
-    pub fn is_off(&self) -> bool
+    fn parse_topic<'a>(topic: &'a str, options: &ParseOptions) -> Result<TopicParts<'a>, TopicMismatch<'a>>
     {
-        self.changed.front().map(|state| state.until.map(|dt| dt > Local::now()).unwrap_or(true) && match state.value { PinValue::Digital(v) => !v, PinValue::Analog(v) => v == 0, _ => false}).unwrap_or(false)
+        let paths: Vec<&str> = split_topic(topic).collect();
+        let (rest, kind, pin) = match paths.as_slice() {
+            [rest @ .., kind, pin] if !kind.is_empty() => (rest, *kind, *pin),
+            _ => return Err(TopicMismatch { segment: 1, expected: TopicSegment::KindAndPin, found: paths.first().copied(),
+                error: PinError::InvalidTopic("Expected value type and pin") })
+        };
+        let pin = pin.parse::<u8>().map_err(|_| TopicMismatch { segment: paths.len(), expected: TopicSegment::Pin, found: Some(pin),
+            error: PinError::InvalidTopic("Unable to parse integer") })?;
+        let rest = match rest {
+            [rest @ .., namespace] if options.kind_namespaces.iter().any(|n| n == namespace) => rest,
+            _ => rest
+        };
+        let (node, timeout) = match rest {
+            [node @ .., "current"] | [node @ .., "last"] => (node, None),
+            [node @ .., "current", "timeout", timeout] => (node, Some(*timeout)),
+            [node @ .., "timeout", timeout] => (node, Some(*timeout)),
+            [.., "timeout"] => return Err(TopicMismatch { segment: rest.len() + 1, expected: TopicSegment::Timeout, found: None,
+                error: PinError::InvalidTopic("Expected current, last or timeout") }),
+            _ => {
+                let error = if rest.contains(&"current") || rest.contains(&"last") {
+                    PinError::InvalidTopic("Unexpected segment before value type")
+                } else {
+                    PinError::InvalidTopic("Expected current, last or timeout")
+                };
+                return Err(TopicMismatch { segment: rest.len().max(1), expected: TopicSegment::Operation, found: rest.last().copied(), error });
+            }
+        };
+        let node = match join_node(node) {
+            Some(node) => node,
+            None => return Err(TopicMismatch { segment: 1, expected: TopicSegment::Node, found: None,
+                error: PinError::InvalidTopic(if timeout.is_some() { "Unknown node after timeout" } else { "Unknown node" }) })
+        };
+        let timeout = match timeout {
+            Some(timeout) => Some(timeout.parse::<u32>().map_err(|_| TopicMismatch { segment: rest.len(), expected: TopicSegment::Timeout,
+                found: Some(timeout), error: PinError::InvalidValue("Unable to parse timeout") })?),
+            None => None
+        };
+        Ok(TopicParts { node, kind, pin, timeout })
     }
 
-    pub fn get_last_changed_dt(&self) -> Option<DateTime<Local>>
+    /**
+     * node1/current/env/5 t=21.5;h=55;a=300
+     * one state per known key (t temperature, h humidity, a analog), unknown keys are skipped
+     * or rejected with UnknownKind depending on skip_unknown
+     */
+    pub fn from_combined_message(topic: &str, payload: &str, skip_unknown: bool) -> Result<Vec<PinStateIn<Tz>>, PinError>
     {
-        self.changed.front().map(|s| s.dt)
+        PinOperationIn::from_combined_message_with_options(topic, payload, &ParserRegistry::default(), &ParseOptions::default(), skip_unknown)
     }
 
-    pub fn get_last_changed_value(&self) -> Option<PinValue>
+    pub fn from_combined_message_with_options(topic: &str, payload: &str, registry: &ParserRegistry, options: &ParseOptions, skip_unknown: bool) -> Result<Vec<PinStateIn<Tz>>, PinError>
     {
-        self.changed.front().map(|state| state.value.clone())
-        //.and_then(|state| match state.value { PinValue::Digital(v) => Some(v as u16), PinValue::Analog(v) => Some(v), _ => None})
+        PinOperationIn::from_combined_message_with_clock::<SystemClock>(topic, payload, registry, options, skip_unknown)
     }
 
-    pub fn get_last_changed(&self) -> Option<PinState>
+    /**
+     * there is no built in humidity type, h is parsed by the registry's humidity parser and is an unknown key without one
+     */
+    pub fn from_combined_message_with_clock<C: Clock<Tz>>(topic: &str, payload: &str, registry: &ParserRegistry, options: &ParseOptions, skip_unknown: bool) -> Result<Vec<PinStateIn<Tz>>, PinError>
     {
-        self.changed.front().map(|state| state.clone())
+        options.check_topic(topic)?;
+        options.check_payload(payload)?;
+        let dt = C::now();
+        let parts = PinOperationIn::<Tz>::parse_topic(topic, options)?;
+        let until = match parts.timeout {
+            Some(timeout) => Some(options.until(&dt, timeout)?),
+            None => None
+        };
+        let mut states = Vec::new();
+        for pair in payload.split(';').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let (key, message) = pair.split_once('=').ok_or(PinError::InvalidValue("Expected key=value in combined payload"))?;
+            let message = message.trim();
+            let value = match key.trim() {
+                "t" => registry.parse("temperature", message)?,
+                "h" if registry.has_parser("humidity") => registry.parse("humidity", message)?,
+                "a" => registry.parse("analog", message)?,
+                _ if skip_unknown => continue,
+                key => return Err(PinError::UnknownKind(key.to_string()))
+            };
+            states.push(PinStateIn { pin: parts.pin, value, dt: dt.clone(), until: until.clone() });
+        }
+        Ok(states)
     }
-}
 
+    /**
+     * node1/current/8 {"type":"analog","value":512}
+     * node1/current/5 {"type":"digital","value":true,"timeout":3600}
+     */
+    pub fn from_json_message(topic: &str, json: &str) -> Result<PinOperationIn<Tz>, PinError>
+    {
+        PinOperationIn::from_json_message_with_options(topic, json, &ParserRegistry::default(), &ParseOptions::default())
+    }
 
-#[cfg(test)]
-mod tests
-{
-    // Note this useful idiom: importing names from outer (for mod tests) scope.
-    use super::*;
-    use chrono::Duration;
+    pub fn from_json_message_with_options(topic: &str, json: &str, registry: &ParserRegistry, options: &ParseOptions) -> Result<PinOperationIn<Tz>, PinError>
+    {
+        PinOperationIn::from_json_message_with_clock::<SystemClock>(topic, json, registry, options)
+    }
 
-    #[test]
-    fn test_pin_collection_is_on_off()
+    /**
+     * validated like from_parts_with_clock: kinds go through the registry, the timeout is capped by options.max_timeout
+     */
+    pub fn from_json_message_with_clock<C: Clock<Tz>>(topic: &str, json: &str, registry: &ParserRegistry, options: &ParseOptions) -> Result<PinOperationIn<Tz>, PinError>
     {
-        let mut col = PinCollection::default();
-        assert_eq!(col.is_on(), false);
+        options.check_topic(topic)?;
+        options.check_json(json)?;
+        let paths: Vec<&str> = split_topic(topic).collect();
+        let (node, pin) = match paths.as_slice() {
+            [node @ .., "current", pin] => (node, *pin),
+            _ => return Err(PinError::InvalidTopic("Expected current"))
+        };
+        let pin = pin.parse::<u8>().map_err(|_| PinError::InvalidTopic("Unable to parse integer"))?;
+        let node = join_node(node).ok_or(PinError::InvalidTopic("Unknown node"))?;
 
-        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(20.5_f32)), dt: Local::now(), until: None});
-        assert_eq!(col.is_on(), false);
-        assert_eq!(col.is_off(), false);
+        let body = json::parse(json).map_err(|_| PinError::InvalidValue("Unable to parse json payload"))?;
+        let kind = body["type"].as_str().ok_or(PinError::InvalidValue("Expected type in json payload"))?;
+        let message = match &body["value"] {
+            json::JsonValue::Boolean(v) => (*v as u8).to_string(),
+            json::JsonValue::Null => return Err(PinError::InvalidValue("Expected value in json payload")),
+            v => v.as_str().map(|s| s.to_string()).unwrap_or_else(|| v.dump())
+        };
+        let timeout = match &body["timeout"] {
+            json::JsonValue::Null => None,
+            v => Some(v.as_u32().ok_or(PinError::InvalidValue("Unable to parse timeout"))?)
+        };
+        PinOperationIn::operation_at(TopicParts { node, kind, pin, timeout }, &message, registry, options, C::now(), topic, json)
+    }
 
-        col.push(&PinState {pin: 1_u8, value: PinValue::Analog(123_u16), dt: Local::now(), until: None});
-        assert_eq!(col.is_on(), true);
-        assert_eq!(col.is_off(), false);
+    /**
+     * toggle operations flip the last digital value of the pin, turning it on when there is no prior state
+     */
+    pub fn resolve_toggle<C: Clock<Tz>>(&self, col: &PinCollectionIn<Tz, C>) -> PinStateIn<Tz>
+    {
+        if !self.toggle {
+            return self.pin_state.clone();
+        }
+        let last = col.last_digital_value().unwrap_or(false);
+        PinStateIn { value: PinValue::Digital(!last), ..self.pin_state.clone() }
+    }
 
-        col.push(&PinState {pin: 1_u8, value: PinValue::Analog(0_u16), dt: Local::now(), until: None});
-        assert_eq!(col.is_on(), false);
-        assert_eq!(col.is_off(), true);
+    pub fn validate(&self, max_pin: u8) -> Result<(), PinError>
+    {
+        if self.node.is_empty() {
+            return Err(PinError::InvalidTopic("Empty node"));
+        }
+        if self.pin_state.pin > max_pin {
+            return Err(PinError::InvalidPin(self.pin_state.pin));
+        }
+        Ok(())
+    }
 
-        col.push(&PinState {pin: 1_u8, value: PinValue::Analog(123_u16), dt: Local::now(), until: Some(Local::now() + Duration::seconds(3))});
-        assert_eq!(col.is_on(), true);
-        assert_eq!(col.is_off(), false);
+    /**
+     * digital, analog and toggle write the pin, temperature and voltage are readings
+     */
+    pub fn validate_against_mode(&self, mode: PinMode) -> Result<(), PinError>
+    {
+        let is_write = self.toggle || self.pin_state.value.is_digital() || self.pin_state.value.is_analog();
+        match mode {
+            PinMode::Input if is_write => Err(PinError::InvalidValue("Unable to write an input pin")),
+            PinMode::Output if !is_write => Err(PinError::InvalidValue("Unable to read from an output pin")),
+            _ => Ok(())
+        }
+    }
 
-        // turn off first
-        col.push(&PinState {pin: 1_u8, value: PinValue::Analog(0_u16), dt: Local::now(), until: None});
-        col.push(&PinState {pin: 1_u8, value: PinValue::Analog(123_u16), dt: Local::now(), until: Some(Local::now() - Duration::seconds(3))});
-        assert_eq!(col.is_on(), false);
-        assert_eq!(col.is_off(), false);
+    /**
+     * describes why a topic does or does not parse, segments are counted from 1 over the non empty segments
+     */
+    pub fn explain(topic: &str, payload: &str) -> String
+    {
+        PinOperationIn::<Tz>::explain_with_options(topic, payload, &ParserRegistry::default(), &ParseOptions::default())
     }
 
+    pub fn explain_with_options(topic: &str, payload: &str, registry: &ParserRegistry, options: &ParseOptions) -> String
+    {
+        PinOperationIn::<Tz>::explain_with_clock::<SystemClock>(topic, payload, registry, options)
+    }
 
-    #[test]
-    fn test_pin_collection_get_average_temperature()
+    /**
+     * timeouts are resolved against C like from_parts_with_clock
+     */
+    pub fn explain_with_clock<C: Clock<Tz>>(topic: &str, payload: &str, registry: &ParserRegistry, options: &ParseOptions) -> String
     {
-        let mut col = PinCollection::default();
-        let since = Local::now() - Duration::seconds(100);
-        assert_eq!(col.get_average_temperature(&since), None);
+        if let Err(error) = options.check_topic(topic).and_then(|_| options.check_payload(payload)) {
+            return error.to_string();
+        }
+        let parts = match PinOperationIn::<Tz>::parse_topic(topic, options) {
+            Ok(parts) => parts,
+            Err(mismatch) => return mismatch.to_string()
+        };
+        let dt = C::now();
+        if let Err(error) = parts.timeout.map(|timeout| options.until(&dt, timeout)).transpose() {
+            return error.to_string();
+        }
+        let kind = parts.kind;
+        match PinOperationIn::<Tz>::operation_at(parts, payload, registry, options, dt, topic, payload) {
+            Ok(op) => format!("ok: node '{}', pin {}, {} value {}", op.node, op.pin_state.pin,
+                if op.toggle { "toggle" } else { op.pin_state.value.kind() }, op.pin_state.value.to_payload()),
+            Err(PinError::UnknownKind(kind)) => format!("unknown value type '{}' at segment {}", kind, split_topic(topic).count() - 1),
+            Err(PinError::InvalidValue(reason)) => format!("invalid payload '{}' for {}: {}", payload, kind, reason),
+            Err(error) => error.to_string()
+        }
+    }
 
-        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(20_f32)), dt: Local::now(), until: None});
-        assert_eq!(col.get_average_temperature(&since).unwrap(), Temperature::new(20_f32));
+    pub fn is_broadcast(&self) -> bool
+    {
+        self.is_broadcast_to(BROADCAST_NODE)
+    }
 
-        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(10_f32)), dt: Local::now(), until: None});
-        assert_eq!(col.get_average_temperature(&since).unwrap(), Temperature::new(15_f32));
+    pub fn is_broadcast_to(&self, token: &str) -> bool
+    {
+        self.node == token
+    }
 
-        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(18_f32)), dt: Local::now(), until: None});
-        assert_eq!(col.get_average_temperature(&since).unwrap(), Temperature::new(16_f32));
+    pub fn expand_broadcast(&self, nodes: &[String]) -> Vec<PinOperationIn<Tz>>
+    {
+        self.expand_broadcast_to(BROADCAST_NODE, nodes)
+    }
 
-        assert_eq!(col.get_average_temperature(&(since + Duration::seconds(200))), None);
+    /**
+     * one operation per node when the node is token, otherwise just this operation
+     */
+    pub fn expand_broadcast_to(&self, token: &str, nodes: &[String]) -> Vec<PinOperationIn<Tz>>
+    {
+        if !self.is_broadcast_to(token) {
+            return vec![self.clone()];
+        }
+        nodes.iter().map(|node| PinOperationIn { node: node.clone(), ..self.clone() }).collect()
+    }
+}
+
+/**
+ * builds topic and payload in the format understood by PinOperation::from_message
+ *
+ * node1/current/analog/8 512
+ * node1/timeout/3600/analog/8 512
+ */
+#[derive(Default, Debug, Clone)]
+pub struct TopicBuilder
+{
+    node: Option<String>,
+    pin: Option<u8>,
+    value: Option<PinValue>,
+    timeout: Option<u32>
+}
+
+impl TopicBuilder
+{
+    pub fn new() -> TopicBuilder
+    {
+        TopicBuilder::default()
+    }
+
+    pub fn node(mut self, node: &str) -> TopicBuilder
+    {
+        self.node = Some(node.to_string());
+        self
+    }
+
+    pub fn pin(mut self, pin: u8) -> TopicBuilder
+    {
+        self.pin = Some(pin);
+        self
+    }
+
+    pub fn value(mut self, value: &PinValue) -> TopicBuilder
+    {
+        self.value = Some(value.clone());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: u32) -> TopicBuilder
+    {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /**
+     * returns (topic, payload)
+     */
+    pub fn build(&self) -> Result<(String, String), PinError>
+    {
+        let node = self.node.as_ref().filter(|node| !node.is_empty()).ok_or(PinError::InvalidTopic("Unknown node"))?;
+        let pin = self.pin.ok_or(PinError::InvalidTopic("Unknown pin"))?;
+        let value = self.value.as_ref().ok_or(PinError::InvalidValue("Missing value"))?;
+        let operation = match self.timeout {
+            Some(timeout) => format!("timeout/{}", timeout),
+            None => "current".to_string()
+        };
+        Ok((format!("{}/{}/{}/{}", node, operation, value.kind(), pin), value.to_payload()))
+    }
+}
+
+/**
+ * empty segments from leading, trailing or doubled slashes are dropped
+ */
+fn split_topic(topic: &str) -> impl Iterator<Item = &str>
+{
+    topic.split('/').filter(|segment| !segment.is_empty())
+}
+
+/**
+ * nodes can span several segments e.g. building/floor2/node1
+ */
+fn join_node(segments: &[&str]) -> Option<String>
+{
+    if segments.is_empty() {
+        return None;
+    }
+    Some(segments.join("/"))
+}
+
+/**
+ * oldest first, states with the same dt keep their order
+ */
+pub fn sort_states_by_time<Tz: Zone>(states: &mut [PinStateIn<Tz>])
+{
+    states.sort_by_key(|state| state.dt.clone());
+}
+
+pub fn filter_invalid<Tz: Zone>(ops: &[PinOperationIn<Tz>], max_pin: u8) -> Vec<(usize, PinError)>
+{
+    ops.iter().enumerate()
+        .filter_map(|(i, op)| op.validate(max_pin).err().map(|e| (i, e)))
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Direction
+{
+    Rising,
+    Falling,
+    Unchanged
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CrossDirection
+{
+    Rising,
+    Falling,
+    Either
+}
+
+#[derive(new, Debug, PartialEq, Clone)]
+pub struct ThresholdEvent
+{
+    pub pin: u8,
+    pub threshold: f32,
+    pub direction: CrossDirection,
+    pub value: Temperature
+}
+
+#[derive(new, Debug, PartialEq, Clone)]
+pub struct TemperatureStats
+{
+    pub average: Temperature,
+    pub min: Temperature,
+    pub max: Temperature,
+    pub count: usize
+}
+
+/**
+ * effect of PinRegistry::apply, previous and current are the effective values around the operation
+ */
+#[derive(new, Debug, PartialEq, Clone)]
+pub struct ApplyResult
+{
+    pub pin: u8,
+    pub changed: bool,
+    pub previous: Option<PinValue>,
+    pub current: Option<PinValue>
+}
+
+/**
+ * everything a dashboard tile needs, taken from one borrow of the collection
+ */
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(bound(serialize = "DateTime<Tz>: Serialize")))]
+pub struct PinSnapshot<Tz: Zone>
+{
+    pub is_on: bool,
+    pub is_off: bool,
+    pub last_changed_dt: Option<DateTime<Tz>>,
+    pub last_changed_value: Option<PinValue>,
+    pub average_temperature: Option<Temperature>,
+    pub sample_count: usize
+}
+
+impl<Tz: Zone> Default for PinSnapshot<Tz>
+{
+    fn default() -> PinSnapshot<Tz>
+    {
+        PinSnapshot { is_on: false, is_off: false, last_changed_dt: None, last_changed_value: None, average_temperature: None, sample_count: 0 }
+    }
+}
+
+pub type PinCollection = PinCollectionIn<Local>;
+pub type UtcPinCollection = PinCollectionIn<Utc>;
+
+const BUFFER_CAPACITY: usize = 20;
+
+type SequencedStates<Tz> = ArrayDeque<[(u64, PinStateIn<Tz>); BUFFER_CAPACITY], Wrapping>;
+
+/**
+ * one wrapping buffer per value kind so a burst of one kind does not evict the others,
+ * up to CAPACITY samples per kind i.e. 4 * CAPACITY in total, iteration is newest first across all kinds
+ */
+#[derive(Debug)]
+struct KindStates<Tz: Zone>
+{
+    deques: [SequencedStates<Tz>; 4],
+    sequence: u64
+}
+
+/**
+ * merges the per kind buffers lazily, each is already newest first so only their heads are compared
+ */
+#[derive(Clone)]
+struct KindStatesIter<'a, Tz: Zone>
+{
+    iters: [arraydeque::Iter<'a, (u64, PinStateIn<Tz>)>; 4]
+}
+
+impl<'a, Tz: Zone> Iterator for KindStatesIter<'a, Tz>
+{
+    type Item = &'a PinStateIn<Tz>;
+
+    fn next(&mut self) -> Option<&'a PinStateIn<Tz>>
+    {
+        let newest = (0..self.iters.len())
+            .filter_map(|i| self.iters[i].clone().next().map(|(sequence, _)| (*sequence, i)))
+            .max()?.1;
+        self.iters[newest].next().map(|(_, state)| state)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>)
+    {
+        let len = self.iters.iter().map(|iter| iter.len()).sum();
+        (len, Some(len))
+    }
+}
+
+impl<'a, Tz: Zone> DoubleEndedIterator for KindStatesIter<'a, Tz>
+{
+    fn next_back(&mut self) -> Option<&'a PinStateIn<Tz>>
+    {
+        let oldest = (0..self.iters.len())
+            .filter_map(|i| self.iters[i].clone().next_back().map(|(sequence, _)| (*sequence, i)))
+            .min()?.1;
+        self.iters[oldest].next_back().map(|(_, state)| state)
+    }
+}
+
+impl<'a, Tz: Zone> ExactSizeIterator for KindStatesIter<'a, Tz> {}
+
+impl<Tz: Zone> Default for KindStates<Tz>
+{
+    fn default() -> KindStates<Tz>
+    {
+        KindStates { deques: [ArrayDeque::new(), ArrayDeque::new(), ArrayDeque::new(), ArrayDeque::new()], sequence: 0 }
+    }
+}
+
+impl<Tz: Zone> KindStates<Tz>
+{
+    fn slot(value: &PinValue) -> usize
+    {
+        match value { PinValue::Temperature(_) => 0, PinValue::Analog(_) => 1, PinValue::Digital(_) => 2, PinValue::Voltage(_) => 3 }
+    }
+
+    fn push_front(&mut self, state: PinStateIn<Tz>)
+    {
+        self.sequence += 1;
+        self.deques[Self::slot(&state.value)].push_front((self.sequence, state));
+    }
+
+    fn iter(&self) -> KindStatesIter<'_, Tz>
+    {
+        let [temperature, analog, digital, voltage] = &self.deques;
+        KindStatesIter { iters: [temperature.iter(), analog.iter(), digital.iter(), voltage.iter()] }
+    }
+
+    fn iter_mut(&mut self) -> impl Iterator<Item = &mut PinStateIn<Tz>>
+    {
+        self.deques.iter_mut().flat_map(|deque| deque.iter_mut()).map(|(_, state)| state)
+    }
+
+    fn front(&self) -> Option<&PinStateIn<Tz>>
+    {
+        self.deques.iter().filter_map(|deque| deque.front()).max_by_key(|(sequence, _)| *sequence).map(|(_, state)| state)
+    }
+
+    fn back(&self) -> Option<&PinStateIn<Tz>>
+    {
+        self.deques.iter().filter_map(|deque| deque.back()).min_by_key(|(sequence, _)| *sequence).map(|(_, state)| state)
+    }
+
+    fn len(&self) -> usize
+    {
+        self.deques.iter().map(|deque| deque.len()).sum()
+    }
+
+    fn clear(&mut self)
+    {
+        self.deques.iter_mut().for_each(|deque| deque.clear());
+    }
+
+    fn retain<F: Fn(&PinStateIn<Tz>) -> bool>(&mut self, keep: F)
+    {
+        self.deques.iter_mut().for_each(|deque| deque.retain(|(_, state)| keep(state)));
+    }
+}
+
+#[derive(Debug)]
+pub struct PinCollectionIn<Tz: Zone, C: Clock<Tz> = SystemClock>
+{
+    states: KindStates<Tz>,
+    changed: ArrayDeque<[PinStateIn<Tz>; BUFFER_CAPACITY], Wrapping>,
+    ignored_change_pins: Vec<u8>,
+    temperature_threshold: Option<(f32, CrossDirection)>,
+    labels: HashMap<u8, String>,
+    min_sample_interval: Option<Duration>,
+    cooldown: Option<Duration>,
+    baseline: Vec<PinStateIn<Tz>>,
+    analog_level_delta: u16,
+    analog_baseline_deviation: u16,
+    version: u64,
+    clock: PhantomData<C>
+}
+
+impl<Tz: Zone, C: Clock<Tz>> Default for PinCollectionIn<Tz, C>
+{
+    fn default() -> PinCollectionIn<Tz, C>
+    {
+        PinCollectionIn {
+            states: KindStates::default(),
+            changed: ArrayDeque::new(),
+            ignored_change_pins: Vec::new(),
+            temperature_threshold: None,
+            labels: HashMap::new(),
+            min_sample_interval: None,
+            cooldown: None,
+            baseline: Vec::new(),
+            analog_level_delta: 0,
+            analog_baseline_deviation: 0,
+            version: 0,
+            clock: PhantomData
+        }
+    }
+}
+
+impl<Tz: Zone, C: Clock<Tz>> PinCollectionIn<Tz, C>
+{
+    /**
+     * samples kept per value kind and changes kept, older entries wrap out
+     */
+    pub const CAPACITY: usize = BUFFER_CAPACITY;
+
+    /**
+     * states are newest first, each kind keeps up to CAPACITY of them
+     */
+    pub fn new(states: ArrayDeque<[PinStateIn<Tz>; BUFFER_CAPACITY], Wrapping>, changed: ArrayDeque<[PinStateIn<Tz>; BUFFER_CAPACITY], Wrapping>) -> PinCollectionIn<Tz, C>
+    {
+        let mut col = PinCollectionIn::default();
+        for state in states.into_iter().rev() {
+            col.states.push_front(state);
+        }
+        col.changed = changed;
+        col
+    }
+
+    /**
+     * states are pushed oldest first whatever their order in the slice
+     */
+    pub fn from_states(states: &[PinStateIn<Tz>]) -> PinCollectionIn<Tz, C>
+    {
+        let mut states = states.to_vec();
+        sort_states_by_time(&mut states);
+        let mut col = PinCollectionIn::default();
+        for state in states.iter() {
+            col.push(state);
+        }
+        col
+    }
+
+    /**
+     * states of these pins are kept but never recorded as changes
+     */
+    pub fn with_ignored_change_pins(mut self, pins: &[u8]) -> PinCollectionIn<Tz, C>
+    {
+        self.ignored_change_pins = pins.to_vec();
+        self
+    }
+
+    /**
+     * states of the same pin and kind arriving faster than interval are not stored,
+     * digital changes are still recorded
+     */
+    pub fn with_min_sample_interval(mut self, interval: Duration) -> PinCollectionIn<Tz, C>
+    {
+        self.min_sample_interval = Some(interval);
+        self
+    }
+
+    /**
+     * analog readings moving at least min_delta from the last analog change are recorded as changes too,
+     * 0 records only zero crossings
+     */
+    pub fn with_analog_level_tracking(mut self, min_delta: u16) -> PinCollectionIn<Tz, C>
+    {
+        self.analog_level_delta = min_delta;
+        self
+    }
+
+    /**
+     * analog readings deviating more than threshold from the average of the stored analog samples
+     * are recorded as changes too, catches slow drift, 0 disables it
+     */
+    pub fn with_baseline_deviation(mut self, threshold: u16) -> PinCollectionIn<Tz, C>
+    {
+        self.analog_baseline_deviation = threshold;
+        self
+    }
+
+    /**
+     * minimum off time after an off change before the pin may be on again
+     */
+    pub fn with_cooldown(mut self, duration: Duration) -> PinCollectionIn<Tz, C>
+    {
+        self.cooldown = Some(duration);
+        self
+    }
+
+    /**
+     * human readable names e.g. 5 => living_room_heater
+     */
+    pub fn with_labels(mut self, labels: HashMap<u8, String>) -> PinCollectionIn<Tz, C>
+    {
+        self.labels = labels;
+        self
+    }
+
+    pub fn label(&self, pin: u8) -> Option<&str>
+    {
+        self.labels.get(&pin).map(|label| label.as_str())
+    }
+
+    /**
+     * push reports an event when a temperature reading crosses the threshold in the given direction,
+     * reaching the threshold counts as above it
+     */
+    pub fn set_temperature_threshold(&mut self, value: f32, direction: CrossDirection)
+    {
+        self.temperature_threshold = Some((value, direction));
+    }
+
+    pub fn push(&mut self, state: &PinStateIn<Tz>) -> Option<ThresholdEvent>
+    {
+        let event = self.threshold_crossing(state);
+        if !self.ignored_change_pins.contains(&state.pin) && self.is_change(state) {
+            self.changed.push_front(state.clone());
+            self.version += 1;
+        }
+        if self.is_sample_due(state) {
+            self.states.push_front(state.clone());
+        }
+        event
+    }
+
+    /**
+     * records the state as a change even when push would not e.g. heartbeats or a full analog history
+     */
+    pub fn push_forced(&mut self, state: &PinStateIn<Tz>) -> Option<ThresholdEvent>
+    {
+        let event = self.threshold_crossing(state);
+        self.changed.push_front(state.clone());
+        self.version += 1;
+        self.states.push_front(state.clone());
+        event
+    }
+
+    pub fn capacity(&self) -> usize
+    {
+        Self::CAPACITY
+    }
+
+    /**
+     * stored samples across all kinds, up to CAPACITY per kind so at most 4 * CAPACITY
+     */
+    pub fn len(&self) -> usize
+    {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.len() == 0
+    }
+
+    /**
+     * bumped whenever push records a change, compare with a cached value to skip unchanged pins
+     */
+    pub fn version(&self) -> u64
+    {
+        self.version
+    }
+
+    fn is_sample_due(&self, state: &PinStateIn<Tz>) -> bool
+    {
+        let interval = match self.min_sample_interval { Some(interval) => interval, None => return true };
+        self.states.iter()
+            .find(|s| s.pin == state.pin && s.value.kind() == state.value.kind())
+            .map(|s| state.dt.clone() - s.dt.clone() >= interval)
+            .unwrap_or(true)
+    }
+
+    fn threshold_crossing(&self, state: &PinStateIn<Tz>) -> Option<ThresholdEvent>
+    {
+        let (threshold, direction) = self.temperature_threshold?;
+        let current = match &state.value { PinValue::Temperature(v) => v.clone(), _ => return None };
+        let previous = self.states.iter()
+            .find_map(|state| if let PinValue::Temperature(v) = &state.value { Some(v.value) } else { None })?;
+        let crossed = if previous < threshold && current.value >= threshold {
+            CrossDirection::Rising
+        } else if previous >= threshold && current.value < threshold {
+            CrossDirection::Falling
+        } else {
+            return None;
+        };
+        if direction != CrossDirection::Either && direction != crossed {
+            return None;
+        }
+        Some(ThresholdEvent::new(state.pin, threshold, crossed, current))
+    }
+
+    /**
+     * combines both histories in time order, keeping the most recent entries when over capacity,
+     * recorded changes of both sides are kept (a change with the same pin, value and dt only once) unless interleaving
+     * makes one repeat the value the merged history already changed to, samples without a recorded change are
+     * checked for changes the interleaving brings in, the version counts only changes self did not have,
+     * min_sample_interval is not applied and the baseline is untouched
+     */
+    pub fn merge(&mut self, other: &PinCollectionIn<Tz, C>)
+    {
+        let mut changes: Vec<(bool, PinStateIn<Tz>)> = Vec::new();
+        for changed in [&self.changed, &other.changed].iter() {
+            for (i, change) in changed.iter().enumerate() {
+                if changes.iter().any(|(_, state)| state.same_reading(change) && state.dt == change.dt) {
+                    continue;
+                }
+                // recorded although it repeats the previous change of its kind e.g. push_forced
+                let repeated = changed.iter().skip(i + 1).find(|s| s.value.kind() == change.value.kind())
+                    .map(|s| s.same_reading(change))
+                    .unwrap_or(false);
+                changes.push((repeated, change.clone()));
+            }
+        }
+        let samples: Vec<PinStateIn<Tz>> = self.states.iter().rev().chain(other.states.iter().rev()).cloned().collect();
+        let mut entries: Vec<(Option<bool>, PinStateIn<Tz>)> = changes.into_iter().map(|(repeated, state)| (Some(repeated), state))
+            .chain(samples.into_iter().map(|state| (None, state)))
+            .collect();
+        // at the same dt a recorded change goes first so its own sample is not detected as a change again
+        entries.sort_by_key(|(repeated, state)| (state.dt.clone(), repeated.is_none()));
+        for state in other.baseline.iter() {
+            if !self.baseline.iter().any(|s| s.pin == state.pin && s.value.kind() == state.value.kind() && s.dt >= state.dt) {
+                self.set_baseline(state);
+            }
+        }
+        let previous: Vec<PinStateIn<Tz>> = self.changed.iter().cloned().collect();
+        self.states.clear();
+        self.changed.clear();
+        for (repeated, state) in entries {
+            let is_change = match repeated {
+                Some(repeated) => repeated || !self.changed.iter().find(|s| s.value.kind() == state.value.kind())
+                    .map(|s| s.same_reading(&state))
+                    .unwrap_or(false),
+                None => {
+                    self.states.push_front(state.clone());
+                    let recorded = self.changed.iter().any(|change| change.same_reading(&state) && change.dt == state.dt);
+                    !recorded && !self.ignored_change_pins.contains(&state.pin) && self.is_change(&state)
+                }
+            };
+            if is_change {
+                if !previous.iter().any(|change| change.same_reading(&state) && change.dt == state.dt) {
+                    self.version += 1;
+                }
+                self.changed.push_front(state);
+            }
+        }
+    }
+
+    /**
+     * empties both states and changes, the next push is always recorded as a change
+     */
+    pub fn clear(&mut self)
+    {
+        self.states.clear();
+        self.changed.clear();
+        self.baseline.clear();
+    }
+
+    /**
+     * restores a known state e.g. after a restart, later pushes compare against it but it is not a change itself,
+     * it stands in for the last change until a change is recorded, one baseline is kept per pin and kind
+     */
+    pub fn seed(&mut self, state: &PinStateIn<Tz>)
+    {
+        self.set_baseline(state);
+        self.states.push_front(state.clone());
+    }
+
+    fn set_baseline(&mut self, state: &PinStateIn<Tz>)
+    {
+        self.baseline.retain(|s| s.pin != state.pin || s.value.kind() != state.value.kind());
+        self.baseline.insert(0, state.clone());
+    }
+
+    /**
+     * newest recorded change, falling back to the newest seeded baseline
+     */
+    fn last_changed_state(&self) -> Option<&PinStateIn<Tz>>
+    {
+        self.changed.front().or_else(|| self.baseline.iter().min_by_key(|state| cmp::Reverse(state.dt.clone())))
+    }
+
+    /**
+     * drops states older than cutoff, changes are kept as the baseline for change detection
+     */
+    pub fn clear_states_before(&mut self, cutoff: &DateTime<Tz>)
+    {
+        self.states.retain(|state| state.dt >= *cutoff);
+    }
+
+    fn is_change(&self, state: &PinStateIn<Tz>) -> bool
+    {
+        match state.value {
+            PinValue::Digital(v) => self.last_change(|s| s.value.is_digital())
+                .map(|s| s.value != PinValue::Digital(v))
+                .unwrap_or(true),
+            PinValue::Analog(v) => self.last_change(|s| s.value.is_analog())
+                .map(|s| (s.value.as_u16() == 0) != (v == 0)
+                    || (self.analog_level_delta > 0 && s.value.as_u16().abs_diff(v) >= self.analog_level_delta)
+                    || (s.value.as_u16() != v && self.deviates_from_analog_baseline(v)))
+                .unwrap_or(true),
+            PinValue::Voltage(v) => self.last_change(|s| s.value.is_voltage())
+                .map(|s| s.value.is_on() != (v > 0_f32))
+                .unwrap_or(true),
+            _ => false
+        }
+    }
+
+    fn deviates_from_analog_baseline(&self, value: u16) -> bool
+    {
+        if self.analog_baseline_deviation == 0 {
+            return false;
+        }
+        let samples: Vec<f32> = self.states.iter()
+            .filter_map(|state| if let PinValue::Analog(v) = state.value { Some(v as f32) } else { None })
+            .collect();
+        !samples.is_empty() && (value as f32 - average(&samples)).abs() > self.analog_baseline_deviation as f32
+    }
+
+    fn last_change<F: Fn(&PinStateIn<Tz>) -> bool>(&self, predicate: F) -> Option<&PinStateIn<Tz>>
+    {
+        self.changed.iter().chain(self.baseline.iter()).find(|s| predicate(s))
+    }
+
+    fn temperatures_since(&self, since: &DateTime<Tz>) -> Vec<f32>
+    {
+        self.states.iter()
+            .filter(|state| state.dt > *since )
+            .filter_map(|state|
+                if let PinValue::Temperature(v) = &state.value { Some(v.value) } else { None }
+            )
+            .collect()
+    }
+
+    pub fn get_average_temperature(&self, since: &DateTime<Tz>) -> Option<Temperature>
+    {
+        self.partial_temperature_average(since).map(|(avg, _)| Temperature::new(avg))
+    }
+
+    /**
+     * decimals above 3 are treated as 3
+     */
+    pub fn get_average_temperature_rounded(&self, since: &DateTime<Tz>, decimals: u8) -> Option<f32>
+    {
+        let factor = 10_f32.powi(decimals.min(3) as i32);
+        self.get_average_temperature(since).map(|avg| (avg.value * factor).round() / factor)
+    }
+
+    /**
+     * None until at least min_samples temperature readings fall after since
+     */
+    pub fn get_average_temperature_min_samples(&self, since: &DateTime<Tz>, min_samples: usize) -> Option<Temperature>
+    {
+        self.partial_temperature_average(since)
+            .filter(|(_, count)| *count >= min_samples)
+            .map(|(avg, _)| Temperature::new(avg))
+    }
+
+    /**
+     * average and sample count, combine with helper::combine_averages
+     */
+    pub fn partial_temperature_average(&self, since: &DateTime<Tz>) -> Option<(f32, usize)>
+    {
+        let vec = self.temperatures_since(since);
+        if !vec.is_empty() {
+            return Some((average(&vec), vec.len()));
+        }
+        None
+    }
+
+    /**
+     * average, min, max and sample count of the temperatures after since in a single pass
+     */
+    pub fn get_temperature_stats(&self, since: &DateTime<Tz>) -> Option<TemperatureStats>
+    {
+        let (sum, min, max, count) = self.states.iter()
+            .filter(|state| state.dt > *since )
+            .filter_map(|state| if let PinValue::Temperature(v) = &state.value { Some(v.value) } else { None })
+            .fold((0_f32, f32::MAX, f32::MIN, 0_usize), |(sum, min, max, count), v| (sum + v, min.min(v), max.max(v), count + 1));
+        if count == 0 {
+            return None;
+        }
+        Some(TemperatureStats::new(Temperature::new(sum / count as f32), Temperature::new(min), Temperature::new(max), count))
+    }
+
+    pub fn get_average_analog(&self, since: &DateTime<Tz>) -> Option<f32>
+    {
+        let vec: Vec<f32> = self.states.iter()
+            .filter(|state| state.dt > *since )
+            .filter_map(|state| if let PinValue::Analog(v) = state.value { Some(v as f32) } else { None })
+            .collect();
+        if vec.is_empty() {
+            return None;
+        }
+        Some(average(&vec))
+    }
+
+    pub fn get_average_voltage(&self, since: &DateTime<Tz>) -> Option<f32>
+    {
+        let vec: Vec<f32> = self.states.iter()
+            .filter(|state| state.dt > *since )
+            .filter_map(|state| if let PinValue::Voltage(v) = state.value { Some(v) } else { None })
+            .collect();
+        if vec.is_empty() {
+            return None;
+        }
+        Some(average(&vec))
+    }
+
+    /**
+     * each reading weighs as long as it held, the latest one until now
+     */
+    pub fn get_time_weighted_average_temperature(&self, since: &DateTime<Tz>) -> Option<Temperature>
+    {
+        let mut next_dt = C::now();
+        let mut weighted = 0_f64;
+        let mut total = 0_f64;
+        let mut values = Vec::new();
+        for state in self.states.iter().filter(|state| state.dt > *since) {
+            if let PinValue::Temperature(v) = &state.value {
+                let weight = cmp::max(next_dt.clone() - state.dt.clone(), Duration::zero()).num_milliseconds() as f64;
+                weighted += weight * v.value as f64;
+                total += weight;
+                values.push(v.value);
+                next_dt = cmp::min(next_dt, state.dt.clone());
+            }
+        }
+        if values.is_empty() {
+            return None;
+        }
+        if total == 0_f64 {
+            return Some(Temperature::new(average(&values)));
+        }
+        Some(Temperature::new((weighted / total) as f32))
+    }
+
+    /**
+     * heating metric: integral of how far temperatures after since stayed above base, each reading held until the next, the latest until now
+     */
+    pub fn degree_seconds_above(&self, base: f32, since: &DateTime<Tz>) -> f32
+    {
+        self.degree_seconds(since, |value| value - base)
+    }
+
+    /**
+     * cooling counterpart of degree_seconds_above
+     */
+    pub fn degree_seconds_below(&self, base: f32, since: &DateTime<Tz>) -> f32
+    {
+        self.degree_seconds(since, |value| base - value)
+    }
+
+    fn degree_seconds<F: Fn(f32) -> f32>(&self, since: &DateTime<Tz>, excess: F) -> f32
+    {
+        let mut next_dt = C::now();
+        let mut total = 0_f64;
+        for state in self.states.iter().filter(|state| state.dt > *since) {
+            if let PinValue::Temperature(v) = &state.value {
+                let held = cmp::max(next_dt.clone() - state.dt.clone(), Duration::zero()).num_milliseconds() as f64 / 1000_f64;
+                total += held * excess(v.value).max(0_f32) as f64;
+                next_dt = cmp::min(next_dt, state.dt.clone());
+            }
+        }
+        total as f32
+    }
+
+    pub fn temperature_drift(&self, since: &DateTime<Tz>, split: &DateTime<Tz>) -> Option<f32>
+    {
+        let (before, after): (Vec<&PinStateIn<Tz>>, Vec<&PinStateIn<Tz>>) = self.states.iter()
+            .filter(|state| state.dt > *since && state.value.is_temperature())
+            .partition(|state| state.dt <= *split);
+        let mean = |states: Vec<&PinStateIn<Tz>>| {
+            let values: Vec<f32> = states.iter()
+                .filter_map(|state| if let PinValue::Temperature(v) = &state.value { Some(v.value) } else { None })
+                .collect();
+            if values.is_empty() { None } else { Some(average(&values)) }
+        };
+        Some(mean(after)? - mean(before)?)
+    }
+
+    /**
+     * mean minus median, far from zero when the readings are skewed
+     */
+    pub fn temperature_skew_indicator(&self, since: &DateTime<Tz>) -> Option<f32>
+    {
+        let vec = self.temperatures_since(since);
+        if vec.is_empty() {
+            return None;
+        }
+        Some(average(&vec) - median(&vec))
+    }
+
+    /**
+     * p in 0..=100, interpolated between the closest samples
+     */
+    pub fn temperature_percentile(&self, since: &DateTime<Tz>, p: f32) -> Option<Temperature>
+    {
+        let vec = self.temperatures_since(since);
+        if vec.is_empty() {
+            return None;
+        }
+        Some(Temperature::new(percentile(&vec, p)))
+    }
+
+    /**
+     * average temperature per equal slice of since..now keyed by the slice midpoint, oldest first, empty slices are skipped
+     */
+    pub fn downsample_temperatures(&self, since: &DateTime<Tz>, buckets: usize) -> Vec<(DateTime<Tz>, Temperature)>
+    {
+        let window = (C::now() - since.clone()).num_milliseconds();
+        if buckets == 0 || window <= 0 {
+            return Vec::new();
+        }
+        let mut grouped: Vec<Vec<f32>> = vec![Vec::new(); buckets];
+        for state in self.states.iter().filter(|state| state.dt > *since) {
+            if let PinValue::Temperature(v) = &state.value {
+                let offset = (state.dt.clone() - since.clone()).num_milliseconds() as i128;
+                let index = ((offset * buckets as i128 / window as i128) as usize).min(buckets - 1);
+                grouped[index].push(v.value);
+            }
+        }
+        grouped.iter().enumerate()
+            .filter(|(_, values)| !values.is_empty())
+            .map(|(i, values)| {
+                let midpoint = (window as i128 * (2 * i as i128 + 1) / (2 * buckets as i128)) as i64;
+                (since.clone() + Duration::milliseconds(midpoint), Temperature::new(average(values)))
+            })
+            .collect()
+    }
+
+    pub fn max_reading_gap(&self, since: &DateTime<Tz>) -> Option<Duration>
+    {
+        let mut dts: Vec<DateTime<Tz>> = self.states.iter()
+            .filter(|state| state.dt > *since)
+            .map(|state| state.dt.clone())
+            .collect();
+        dts.sort();
+        dts.windows(2).map(|pair| pair[1].clone() - pair[0].clone()).max()
+    }
+
+    pub fn has_sufficient_samples(&self, since: &DateTime<Tz>, min: usize) -> bool
+    {
+        self.states.iter().filter(|state| state.dt > *since).count() >= min
+    }
+
+    pub fn distinct_analog_count(&self, since: &DateTime<Tz>) -> usize
+    {
+        self.states.iter()
+            .filter(|state| state.dt > *since )
+            .filter_map(|state| if let PinValue::Analog(v) = state.value { Some(v) } else { None })
+            .collect::<HashSet<u16>>()
+            .len()
+    }
+
+    /**
+     * the more frequent digital value after since, None on a tie or without samples
+     */
+    pub fn mode_digital(&self, since: &DateTime<Tz>) -> Option<bool>
+    {
+        self.mode_by(since, |value| if let PinValue::Digital(v) = value { Some(*v) } else { None })
+    }
+
+    /**
+     * the most frequent analog value after since, None on a tie or without samples
+     */
+    pub fn mode_analog(&self, since: &DateTime<Tz>) -> Option<u16>
+    {
+        self.mode_by(since, |value| if let PinValue::Analog(v) = value { Some(*v) } else { None })
+    }
+
+    fn mode_by<T: Hash + Eq + Copy, F: Fn(&PinValue) -> Option<T>>(&self, since: &DateTime<Tz>, extract: F) -> Option<T>
+    {
+        let mut counts: HashMap<T, usize> = HashMap::new();
+        for value in self.states.iter().filter(|state| state.dt > *since).filter_map(|state| extract(&state.value)) {
+            *counts.entry(value).or_insert(0) += 1;
+        }
+        let max = *counts.values().max()?;
+        let mut most_frequent = counts.into_iter().filter(|(_, count)| *count == max);
+        match (most_frequent.next(), most_frequent.next()) {
+            (Some((value, _)), None) => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn smoothed_temperature_rate(&self, since: &DateTime<Tz>, smoothing: usize) -> Option<f32>
+    {
+        let samples: Vec<(DateTime<Tz>, f32)> = self.states.iter().rev()
+            .filter(|state| state.dt > *since )
+            .filter_map(|state|
+                if let PinValue::Temperature(v) = &state.value { Some((state.dt.clone(), v.value)) } else { None }
+            )
+            .collect();
+        if smoothing == 0 || samples.len() < smoothing * 2 {
+            return None;
+        }
+        let origin = samples[0].0.clone();
+        let mean = |group: &[(DateTime<Tz>, f32)]| {
+            let seconds: Vec<f32> = group.iter().map(|(dt, _)| (dt.clone() - origin.clone()).num_milliseconds() as f32 / 1000_f32).collect();
+            let values: Vec<f32> = group.iter().map(|(_, v)| *v).collect();
+            (average(&seconds), average(&values))
+        };
+        let (first_time, first_value) = mean(&samples[..smoothing]);
+        let (last_time, last_value) = mean(&samples[samples.len() - smoothing..]);
+        if last_time <= first_time {
+            return None;
+        }
+        Some((last_value - first_value) / (last_time - first_time))
+    }
+
+    pub fn snapshot(&self, since: &DateTime<Tz>) -> PinSnapshot<Tz>
+    {
+        PinSnapshot {
+            is_on: self.is_on(),
+            is_off: self.is_off(),
+            last_changed_dt: self.get_last_changed_dt(),
+            last_changed_value: self.get_last_changed_value(),
+            average_temperature: self.get_average_temperature(since),
+            sample_count: self.states.iter().filter(|state| state.dt > *since).count()
+        }
+    }
+
+    pub fn is_on(&self) -> bool
+    {
+        self.last_changed_state().map(|state| state.until.clone().map(|dt| dt > C::now()).unwrap_or(true) && match state.value { PinValue::Digital(v) => v, PinValue::Analog(v) => v > 0, PinValue::Voltage(v) => v > 0_f32, _ => false}).unwrap_or(false)
+            && self.can_turn_on()
+    }
+
+    /**
+     * false until the cooldown has elapsed since the last off change, or the seeded off baseline
+     */
+    pub fn can_turn_on(&self) -> bool
+    {
+        let cooldown = match self.cooldown { Some(cooldown) => cooldown, None => return true };
+        self.last_change(|state| !state.value.is_on())
+            .map(|state| C::now() - state.dt.clone() >= cooldown)
+            .unwrap_or(true)
+    }
+
+    /**
+     * until of the current timed on state, None when not on or without a timeout
+     */
+    pub fn active_until(&self) -> Option<DateTime<Tz>>
+    {
+        self.last_changed_state()
+            .filter(|state| state.value.is_on())
+            .and_then(|state| state.until.clone())
+            .filter(|until| *until > C::now())
+    }
+
+    /**
+     * pushes the until of a running timed on state further out, the seeded baseline too while no change is recorded,
+     * false when the current state is off, permanent, already expired or the new until overflows
+     */
+    pub fn extend_timeout(&mut self, additional: Duration) -> bool
+    {
+        let now = C::now();
+        let current = match self.last_changed_state() {
+            Some(state) if state.is_on() && state.until.as_ref().map(|until| *until > now).unwrap_or(false) => state.clone(),
+            _ => return false
+        };
+        let extended = match current.until.clone().and_then(|until| until.checked_add_signed(additional)) {
+            Some(extended) => extended,
+            None => return false
+        };
+        let front = self.changed.front_mut().into_iter();
+        for state in front.chain(self.baseline.iter_mut()).chain(self.states.iter_mut()).filter(|state| **state == current) {
+            state.until = Some(extended.clone());
+        }
+        true
+    }
+
+    /**
+     * the current state reverts once its until passes
+     */
+    pub fn is_temporary(&self) -> bool
+    {
+        self.until_remaining().is_some()
+    }
+
+    pub fn until_remaining(&self) -> Option<Duration>
+    {
+        let now = C::now();
+        self.last_changed_state()
+            .and_then(|state| state.until.clone())
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+
+    pub fn is_off(&self) -> bool
+    {
+        self.last_changed_state().map(|state| state.until.clone().map(|dt| dt > C::now()).unwrap_or(true) && match state.value { PinValue::Digital(v) => !v, PinValue::Analog(v) => v == 0, PinValue::Voltage(v) => v <= 0_f32, _ => false}).unwrap_or(false)
+    }
+
+    pub fn get_last_changed_dt(&self) -> Option<DateTime<Tz>>
+    {
+        self.last_changed_state().map(|s| s.dt.clone())
+    }
+
+    /**
+     * most recent first
+     */
+    pub fn last_n_states(&self, n: usize) -> Vec<PinStateIn<Tz>>
+    {
+        self.states.iter().take(n).cloned().collect()
+    }
+
+    /**
+     * the n most recent temperature readings, most recent first
+     */
+    pub fn last_n_temperatures(&self, n: usize) -> Vec<f32>
+    {
+        self.states.iter()
+            .filter_map(|state| if let PinValue::Temperature(v) = &state.value { Some(v.value) } else { None })
+            .take(n)
+            .collect()
+    }
+
+    pub fn time_since_last_change(&self) -> Option<Duration>
+    {
+        self.last_changed_state().map(|state| C::now() - state.dt.clone())
+    }
+
+    /**
+     * last changed value, or off once its until has passed
+     */
+    pub fn get_effective_value(&self) -> Option<PinValue>
+    {
+        let state = self.last_changed_state()?;
+        if state.until.clone().map(|until| until > C::now()).unwrap_or(true) {
+            return Some(state.value.clone());
+        }
+        Some(match state.value {
+            PinValue::Digital(_) => PinValue::Digital(false),
+            PinValue::Analog(_) => PinValue::Analog(0),
+            PinValue::Voltage(_) => PinValue::Voltage(0_f32),
+            ref value => value.clone()
+        })
+    }
+
+    pub fn get_last_changed_value(&self) -> Option<PinValue>
+    {
+        self.last_changed_state().map(|state| state.value.clone())
+        //.and_then(|state| match state.value { PinValue::Digital(v) => Some(v as u16), PinValue::Analog(v) => Some(v), _ => None})
+    }
+
+    pub fn get_last_changed(&self) -> Option<PinStateIn<Tz>>
+    {
+        self.last_changed_state().cloned()
+    }
+
+    pub fn get_last_changed_labeled(&self) -> Option<(PinStateIn<Tz>, Option<&str>)>
+    {
+        self.last_changed_state().map(|state| (state.clone(), self.label(state.pin)))
+    }
+
+    /**
+     * compares the two most recent analog readings, including those that did not count as a change
+     */
+    pub fn get_last_analog_direction(&self) -> Option<Direction>
+    {
+        let mut analog = self.states.iter().filter_map(|state| if let PinValue::Analog(v) = state.value { Some(v) } else { None });
+        let (last, previous) = (analog.next()?, analog.next()?);
+        Some(match last.cmp(&previous) {
+            cmp::Ordering::Greater => Direction::Rising,
+            cmp::Ordering::Less => Direction::Falling,
+            cmp::Ordering::Equal => Direction::Unchanged
+        })
+    }
+
+    pub fn get_last_changed_digital(&self) -> Option<PinStateIn<Tz>>
+    {
+        self.changed.iter().find(|state| state.value.is_digital()).cloned()
+    }
+
+    pub fn get_last_changed_analog(&self) -> Option<PinStateIn<Tz>>
+    {
+        self.changed.iter().find(|state| state.value.is_analog()).cloned()
+    }
+
+    pub fn last_digital_value(&self) -> Option<bool>
+    {
+        self.states.iter().find_map(|state| if let PinValue::Digital(v) = state.value { Some(v) } else { None })
+    }
+
+    /**
+     * value in effect at the given time i.e. of the newest state not after it
+     */
+    pub fn value_at(&self, at: &DateTime<Tz>) -> Option<PinValue>
+    {
+        self.states.iter().rev()
+            .filter(|state| state.dt <= *at)
+            .max_by_key(|state| state.dt.clone())
+            .map(|state| state.value.clone())
+    }
+
+    /**
+     * linear between the temperature samples around at, None outside the sampled range
+     */
+    pub fn interpolated_temperature_at(&self, at: &DateTime<Tz>) -> Option<Temperature>
+    {
+        let samples = self.states.iter()
+            .filter_map(|state| if let PinValue::Temperature(v) = &state.value { Some((state.dt.clone(), v.value)) } else { None });
+        let before = samples.clone().filter(|(dt, _)| dt <= at).max_by_key(|(dt, _)| dt.clone())?;
+        let after = samples.filter(|(dt, _)| dt >= at).min_by_key(|(dt, _)| dt.clone())?;
+        let span = (after.0 - before.0.clone()).num_milliseconds();
+        if span == 0 {
+            return Some(Temperature::new(before.1));
+        }
+        let ratio = (at.clone() - before.0).num_milliseconds() as f32 / span as f32;
+        Some(Temperature::new(before.1 + (after.1 - before.1) * ratio))
+    }
+
+    /**
+     * how far back the retained history reaches
+     */
+    pub fn oldest_state(&self) -> Option<&PinStateIn<Tz>>
+    {
+        self.states.back()
+    }
+
+    pub fn oldest_dt(&self) -> Option<DateTime<Tz>>
+    {
+        self.states.back().map(|s| s.dt.clone())
+    }
+
+    pub fn last_update_dt(&self) -> Option<DateTime<Tz>>
+    {
+        self.states.front().map(|s| s.dt.clone())
+    }
+
+    pub fn is_stale(&self, max_age: Duration) -> bool
+    {
+        self.last_update_dt().map(|dt| C::now() - dt > max_age).unwrap_or(true)
+    }
+
+    /**
+     * a stuck sensor: at least min_samples (and at least one) readings after since and all of them identical,
+     * temperatures within 0.001
+     */
+    pub fn is_flatlined(&self, since: &DateTime<Tz>, min_samples: usize) -> bool
+    {
+        let values: Vec<&PinValue> = self.states.iter().filter(|state| state.dt > *since).map(|state| &state.value).collect();
+        let first = match values.first() { Some(first) => *first, None => return false };
+        values.len() >= min_samples && values.iter().all(|value| match (first, value) {
+            (PinValue::Temperature(a), PinValue::Temperature(b)) => (a.value - b.value).abs() <= 0.001_f32,
+            (a, b) => a == *b
+        })
+    }
+
+    /**
+     * recorded changes oldest first, samples that were not a change are dropped
+     */
+    pub fn into_changes(self) -> Vec<PinStateIn<Tz>>
+    {
+        self.changed.into_iter().rev().collect()
+    }
+
+    /**
+     * recorded changes oldest first with runs of the same reading collapsed into their earliest entry
+     */
+    pub fn coalesced_changes(&self) -> Vec<PinStateIn<Tz>>
+    {
+        let mut changes: Vec<PinStateIn<Tz>> = Vec::new();
+        for state in self.changed.iter().rev() {
+            if !changes.last().map(|last| last.same_reading(state)).unwrap_or(false) {
+                changes.push(state.clone());
+            }
+        }
+        changes
+    }
+
+    pub fn changes_since(&self, since: &DateTime<Tz>) -> Vec<PinStateIn<Tz>>
+    {
+        self.changed.iter().filter(|state| state.dt > *since).cloned().collect()
+    }
+
+    pub fn change_count(&self, since: &DateTime<Tz>) -> usize
+    {
+        self.changed.iter().filter(|state| state.dt > *since).count()
+    }
+
+    /**
+     * changes per minute between since and now
+     */
+    pub fn toggle_rate_per_minute(&self, since: &DateTime<Tz>) -> f32
+    {
+        let minutes = (C::now() - since.clone()).num_milliseconds() as f32 / 60_000_f32;
+        if minutes <= 0_f32 {
+            return 0_f32;
+        }
+        self.change_count(since) as f32 / minutes
+    }
+
+    pub fn next_expected_sample(&self, since: &DateTime<Tz>) -> Option<DateTime<Tz>>
+    {
+        let dts: Vec<DateTime<Tz>> = self.states.iter()
+            .filter(|state| state.dt > *since)
+            .map(|state| state.dt.clone())
+            .collect();
+        if dts.len() < 2 {
+            return None;
+        }
+        let newest = dts.iter().max()?;
+        let oldest = dts.iter().min()?;
+        Some(newest.clone() + (newest.clone() - oldest.clone()) / (dts.len() as i32 - 1))
+    }
+
+    pub fn on_duration(&self, start: &DateTime<Tz>, end: &DateTime<Tz>) -> Duration
+    {
+        let mut total = Duration::zero();
+        let mut next_dt = end.clone();
+        for state in self.changed.iter() {
+            if state.dt < *end && state.is_on() {
+                let to = state.until.clone().map(|until| cmp::min(until, next_dt.clone())).unwrap_or_else(|| next_dt.clone());
+                let from = cmp::max(state.dt.clone(), start.clone());
+                if to > from {
+                    total = total + (to - from);
+                }
+            }
+            if state.dt <= *start {
+                break;
+            }
+            next_dt = cmp::min(state.dt.clone(), end.clone());
+        }
+        total
+    }
+}
+
+/**
+ * yields the recorded changes, not every sample, oldest first
+ */
+impl<Tz: Zone, C: Clock<Tz>> IntoIterator for PinCollectionIn<Tz, C>
+{
+    type Item = PinStateIn<Tz>;
+    type IntoIter = std::vec::IntoIter<PinStateIn<Tz>>;
+
+    fn into_iter(self) -> Self::IntoIter
+    {
+        self.into_changes().into_iter()
+    }
+}
+
+pub type PinRegistry = PinRegistryIn<Local>;
+pub type UtcPinRegistry = PinRegistryIn<Utc>;
+
+/**
+ * one collection per pin
+ */
+#[derive(Debug)]
+pub struct PinRegistryIn<Tz: Zone, C: Clock<Tz> = SystemClock>
+{
+    collections: HashMap<u8, PinCollectionIn<Tz, C>>
+}
+
+impl<Tz: Zone, C: Clock<Tz>> Default for PinRegistryIn<Tz, C>
+{
+    fn default() -> PinRegistryIn<Tz, C>
+    {
+        PinRegistryIn { collections: HashMap::new() }
+    }
+}
+
+impl<Tz: Zone, C: Clock<Tz>> PinRegistryIn<Tz, C>
+{
+    pub fn push(&mut self, state: &PinStateIn<Tz>) -> Option<ThresholdEvent>
+    {
+        self.collections.entry(state.pin).or_default().push(state)
+    }
+
+    pub fn collection(&self, pin: u8) -> Option<&PinCollectionIn<Tz, C>>
+    {
+        self.collections.get(&pin)
+    }
+
+    /**
+     * pins that received at least one push, ascending
+     */
+    pub fn pins(&self) -> Vec<u8>
+    {
+        let mut pins: Vec<u8> = self.collections.keys().copied().collect();
+        pins.sort_unstable();
+        pins
+    }
+
+    pub fn contains_pin(&self, pin: u8) -> bool
+    {
+        self.collections.contains_key(&pin)
+    }
+
+    /**
+     * toggles are resolved against the pin's own collection
+     */
+    pub fn push_operation(&mut self, op: &PinOperationIn<Tz>) -> Option<ThresholdEvent>
+    {
+        let col = self.collections.entry(op.pin_state.pin).or_default();
+        let state = op.resolve_toggle(col);
+        col.push(&state)
+    }
+
+    /**
+     * like push_operation but reports whether a change was recorded so the caller can decide to act
+     */
+    pub fn apply(&mut self, op: &PinOperationIn<Tz>) -> ApplyResult
+    {
+        let col = self.collections.entry(op.pin_state.pin).or_default();
+        let previous = col.get_effective_value();
+        let version = col.version();
+        let state = op.resolve_toggle(col);
+        col.push(&state);
+        ApplyResult::new(state.pin, col.version() != version, previous, col.get_effective_value())
+    }
+}
+
+/**
+ * rebuilds node => pins from (topic, payload) pairs e.g. a captured mqtt log, unparseable lines are skipped
+ */
+pub fn ingest_messages<I: Iterator<Item = (String, String)>>(lines: I) -> HashMap<String, PinRegistry>
+{
+    ingest_messages_with_errors(lines).0
+}
+
+/**
+ * same as ingest_messages, also returning the index of each skipped line and why
+ */
+pub fn ingest_messages_with_errors<I: Iterator<Item = (String, String)>>(lines: I) -> (HashMap<String, PinRegistry>, Vec<(usize, PinError)>)
+{
+    let mut nodes: HashMap<String, PinRegistry> = HashMap::new();
+    let mut errors = Vec::new();
+    for (i, (topic, payload)) in lines.enumerate() {
+        match PinOperation::from_parts(&topic, &payload) {
+            Ok(op) => { nodes.entry(op.node.clone()).or_default().push_operation(&op); },
+            Err(e) => errors.push((i, e))
+        }
+    }
+    (nodes, errors)
+}
+
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests
+{
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+    use crate::helper::combine_averages;
+
+    #[test]
+    fn test_pin_operation_from_json_message()
+    {
+        let op = PinOperation::from_json_message("node1/current/8", r#"{"type":"analog","value":512}"#).unwrap();
+        assert_eq!(op.node, "node1");
+        assert_eq!(op.pin_state.pin, 8);
+        assert_eq!(op.pin_state.value, PinValue::Analog(512));
+        assert_eq!(op.pin_state.until, None);
+
+        let op = PinOperation::from_json_message("node1/current/5", r#"{"type":"digital","value":true,"timeout":3600}"#).unwrap();
+        assert_eq!(op.pin_state.value, PinValue::Digital(true));
+        let until = op.pin_state.until.unwrap();
+        assert!(until > Local::now() + Duration::seconds(3590) && until <= Local::now() + Duration::seconds(3600));
+
+        let op = PinOperation::from_json_message("node2/current/3", r#"{"type":"temperature","value":"21.5"}"#).unwrap();
+        assert_eq!(op.node, "node2");
+        assert_eq!(op.pin_state.value, PinValue::Temperature(Temperature::new(21.5_f32)));
+
+        assert_eq!(PinOperation::from_json_message("node1/current/8", "512").unwrap_err(), PinError::InvalidValue("Expected type in json payload"));
+        assert_eq!(PinOperation::from_json_message("node1/current/8", "{").unwrap_err(), PinError::InvalidValue("Unable to parse json payload"));
+        assert_eq!(PinOperation::from_json_message("node1/current/8", r#"{"type":"analog"}"#).unwrap_err(), PinError::InvalidValue("Expected value in json payload"));
+        assert_eq!(PinOperation::from_json_message("node1/current/8", r#"{"type":"analog","value":512,"timeout":-1}"#).unwrap_err(), PinError::InvalidValue("Unable to parse timeout"));
+        assert_eq!(PinOperation::from_json_message("node1/8", r#"{"type":"analog","value":512}"#).unwrap_err(), PinError::InvalidTopic("Expected current"));
+    }
+
+    #[test]
+    fn test_pin_operation_from_json_message_with_options()
+    {
+        let mut registry = ParserRegistry::default();
+        registry.register("humidity", |message| {
+            message.parse::<u16>().map(PinValue::Analog).map_err(|_| PinError::InvalidValue("Unable to parse humidity value"))
+        });
+        let options = ParseOptions { max_timeout: Duration::hours(1), ..ParseOptions::default() };
+
+        let op = PinOperation::from_json_message_with_options("node1/current/4", r#"{"type":"humidity","value":55}"#, &registry, &options).unwrap();
+        assert_eq!(op.pin_state.value, PinValue::Analog(55));
+        assert_eq!(PinOperation::from_json_message("node1/current/4", r#"{"type":"humidity","value":55}"#).unwrap_err(),
+            PinError::UnknownKind("humidity".to_string()));
+
+        assert_eq!(PinOperation::from_json_message_with_options("node1/current/5", r#"{"type":"digital","value":1,"timeout":3601}"#, &registry, &options).unwrap_err(),
+            PinError::OutOfRange("timeout 3601s above 3600s".to_string()));
+        let options = ParseOptions { max_json_len: 16, ..options };
+        assert_eq!(PinOperation::from_json_message_with_options("node1/current/8", r#"{"type":"analog","value":512}"#, &registry, &options).unwrap_err(),
+            PinError::OutOfRange("json payload longer than 16 bytes".to_string()));
+
+        let now = FixedClock::now();
+        let op: PinOperation = PinOperationIn::from_json_message_with_clock::<FixedClock>("node1/current/5",
+            r#"{"type":"digital","value":true,"timeout":60}"#, &registry, &ParseOptions::default()).unwrap();
+        assert_eq!(op.pin_state.dt, now);
+        assert_eq!(op.pin_state.until, Some(now + Duration::seconds(60)));
+        assert_eq!(op.raw_payload, r#"{"type":"digital","value":true,"timeout":60}"#);
+    }
+
+    #[test]
+    fn test_topic_builder()
+    {
+        let (topic, payload) = TopicBuilder::new().node("node1").pin(8).value(&PinValue::Analog(512)).timeout(3600).build().unwrap();
+        assert_eq!(topic, "node1/timeout/3600/analog/8");
+        assert_eq!(payload, "512");
+        let op = PinOperation::from_parts_with_registry(&topic, &payload, &ParserRegistry::default()).unwrap();
+        assert_eq!(op.node, "node1");
+        assert_eq!(op.pin_state.pin, 8);
+        assert_eq!(op.pin_state.value, PinValue::Analog(512));
+        assert!(op.pin_state.until.is_some());
+
+        let (topic, payload) = TopicBuilder::new().node("node2").pin(5).value(&PinValue::Digital(true)).build().unwrap();
+        assert_eq!(topic, "node2/current/digital/5");
+        assert_eq!(payload, "1");
+        let op = PinOperation::from_parts_with_registry(&topic, &payload, &ParserRegistry::default()).unwrap();
+        assert_eq!(op.node, "node2");
+        assert_eq!(op.pin_state.value, PinValue::Digital(true));
+        assert_eq!(op.pin_state.until, None);
+
+        let (topic, payload) = TopicBuilder::new().node("node1").pin(3).value(&PinValue::Temperature(Temperature::new(21.5_f32))).build().unwrap();
+        assert_eq!(topic, "node1/current/temperature/3");
+        assert_eq!(payload, "21.50");
+
+        assert_eq!(TopicBuilder::new().pin(8).value(&PinValue::Analog(1)).build().unwrap_err(), PinError::InvalidTopic("Unknown node"));
+        assert_eq!(TopicBuilder::new().node("node1").value(&PinValue::Analog(1)).build().unwrap_err(), PinError::InvalidTopic("Unknown pin"));
+        assert_eq!(TopicBuilder::new().node("node1").pin(8).build().unwrap_err(), PinError::InvalidValue("Missing value"));
+
+        let op = PinOperation::from_parts_with_registry("node1/current/timeout/3600/analog/8", "512", &ParserRegistry::default()).unwrap();
+        assert_eq!(op.node, "node1");
+        assert!(op.pin_state.until.is_some());
+    }
+
+    #[test]
+    fn test_pin_state_now()
+    {
+        let before = Local::now();
+        let state = PinState::now(3, PinValue::Digital(true));
+        assert_eq!(state.pin, 3);
+        assert_eq!(state.value, PinValue::Digital(true));
+        assert!(state.dt >= before && state.dt <= Local::now());
+        assert_eq!(state.until, None);
+
+        let state = PinState::now(3, PinValue::Analog(100)).with_timeout(Duration::seconds(60));
+        let until = state.until.unwrap();
+        assert!(until >= before + Duration::seconds(60) && until <= Local::now() + Duration::seconds(60));
+    }
+
+    #[test]
+    fn test_pin_operation_from_parts()
+    {
+        let op = PinOperation::from_parts("node1/current/analog/3", "2342").unwrap();
+        assert_eq!(op.node, "node1");
+        assert_eq!(op.pin_state.pin, 3);
+        assert_eq!(op.pin_state.value, PinValue::Analog(2342));
+        assert_eq!(op.pin_state.until, None);
+
+        let op = PinOperation::from_parts("node1/current/digital/5", "1").unwrap();
+        assert_eq!(op.pin_state.value, PinValue::Digital(true));
+
+        let op = PinOperation::from_parts("node1/current/temperature/5", "32.23").unwrap();
+        assert_eq!(op.pin_state.value, PinValue::Temperature(Temperature::new(32.23_f32)));
+
+        let op = PinOperation::from_parts("node1/current/timeout/3600/analog/8", "2332").unwrap();
+        assert_eq!(op.node, "node1");
+        assert_eq!(op.pin_state.pin, 8);
+        assert_eq!(op.pin_state.until, Some(op.pin_state.dt + Duration::seconds(3600)));
+
+        assert_eq!(PinOperation::from_parts("node1/current/analog/x", "1").unwrap_err(), PinError::InvalidTopic("Unable to parse integer"));
+        assert_eq!(PinOperation::from_parts("node1/current/analog/3", "x").unwrap_err(), PinError::InvalidValue("Unable to parse analog value"));
+        assert_eq!(PinOperation::from_parts("node1/current/pressure/3", "1").unwrap_err(), PinError::UnknownKind("pressure".to_string()));
+    }
+
+    #[test]
+    fn test_pin_state_to_record()
+    {
+        let now = Utc.timestamp_opt(1_600_000_000, 0).unwrap();
+        let state = UtcPinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(-4.5_f32)), dt: now, until: None};
+        assert_eq!(state.to_record(), CsvRecord { pin: 3, kind: "temperature", value: -4.5_f32, raw: "-4.50".to_string(), until: None });
+
+        let state = UtcPinState {pin: 8_u8, value: PinValue::Analog(512), dt: now, until: Some(now + Duration::seconds(60))};
+        let record = state.to_record();
+        assert_eq!(record, CsvRecord { pin: 8, kind: "analog", value: 512_f32, raw: "512".to_string(), until: Some("2020-09-13T12:27:40+00:00".to_string()) });
+        assert_eq!(CsvRecord::header(), ["pin", "kind", "value", "raw", "until"]);
+    }
+
+    #[test]
+    fn test_pin_state_same_reading()
+    {
+        let now = Local::now();
+        let state = PinState {pin: 5_u8, value: PinValue::Digital(true), dt: now, until: None};
+        let later = PinState {pin: 5_u8, value: PinValue::Digital(true), dt: now + Duration::seconds(30), until: Some(now + Duration::seconds(60))};
+        assert_eq!(state == later, false);
+        assert_eq!(state.same_reading(&later), true);
+        assert_eq!(state.same_reading(&PinState {pin: 6_u8, ..later.clone()}), false);
+        assert_eq!(state.same_reading(&PinState {value: PinValue::Digital(false), ..later}), false);
+    }
+
+    #[test]
+    fn test_pin_state_is_significant_change_from()
+    {
+        let analog = |v| PinState::now(4, PinValue::Analog(v));
+        assert_eq!(analog(500).is_significant_change_from(&analog(503), 5, 0.5_f32), false);
+        assert_eq!(analog(503).is_significant_change_from(&analog(500), 5, 0.5_f32), false);
+        assert_eq!(analog(500).is_significant_change_from(&analog(505), 5, 0.5_f32), false);
+        assert_eq!(analog(500).is_significant_change_from(&analog(506), 5, 0.5_f32), true);
+        assert_eq!(analog(10).is_significant_change_from(&analog(0), 5, 0.5_f32), true);
+
+        let temperature = |v| PinState::now(3, PinValue::Temperature(Temperature::new(v)));
+        assert_eq!(temperature(20_f32).is_significant_change_from(&temperature(20.25_f32), 5, 0.5_f32), false);
+        assert_eq!(temperature(20_f32).is_significant_change_from(&temperature(19_f32), 5, 0.5_f32), true);
+
+        let digital = |v| PinState::now(1, PinValue::Digital(v));
+        assert_eq!(digital(true).is_significant_change_from(&digital(true), 5, 0.5_f32), false);
+        assert_eq!(digital(true).is_significant_change_from(&digital(false), 5, 0.5_f32), true);
+        assert_eq!(digital(true).is_significant_change_from(&analog(1), 5, 0.5_f32), true);
+    }
+
+    #[test]
+    fn test_pin_operation_raw_fields()
+    {
+        let op = PinOperation::from_parts("/node1/current/temperature/5", "21.500").unwrap();
+        assert_eq!(op.raw_topic, "/node1/current/temperature/5");
+        assert_eq!(op.raw_payload, "21.500");
+        assert_eq!(op.pin_state.value.to_payload(), "21.50");
+
+        let json = r#"{"type":"analog","value":512}"#;
+        let op = PinOperation::from_json_message("node1/current/8", json).unwrap();
+        assert_eq!(op.raw_topic, "node1/current/8");
+        assert_eq!(op.raw_payload, json);
+
+        let op = PinOperation::from_state("node1", PinState::now(5, PinValue::Digital(true)));
+        assert_eq!(op.raw_topic, "");
+        assert_eq!(op.raw_payload, "");
+    }
+
+    #[test]
+    fn test_pin_operation_from_state()
+    {
+        let state = PinState::now(5, PinValue::Digital(true));
+        let op = PinOperation::from_state("node1", state.clone());
+        assert_eq!(op.node, "node1");
+        assert_eq!(op.pin_state, state);
+        assert_eq!(op.toggle, false);
+
+        let op: PinOperation = ("node2".to_string(), state.clone()).into();
+        assert_eq!(op.node, "node2");
+        assert_eq!(op.pin_state, state);
+
+        let (topic, payload) = TopicBuilder::new().node(&op.node).pin(op.pin_state.pin).value(&op.pin_state.value).build().unwrap();
+        let parsed = PinOperation::from_parts(&topic, &payload).unwrap();
+        assert_eq!(parsed.node, op.node);
+        assert_eq!(parsed.pin_state.value, op.pin_state.value);
+    }
+
+    #[test]
+    fn test_pin_operation_validate_against_mode()
+    {
+        let digital = PinOperation::from_parts("node1/current/digital/5", "1").unwrap();
+        let analog = PinOperation::from_parts("node1/current/analog/5", "512").unwrap();
+        let toggle = PinOperation::from_parts("node1/current/toggle/5", "").unwrap();
+        let temperature = PinOperation::from_parts("node1/current/temperature/5", "20").unwrap();
+        let voltage = PinOperation::from_parts("node1/current/voltage/5", "3.3").unwrap();
+
+        for op in &[&digital, &analog, &toggle] {
+            assert_eq!(op.validate_against_mode(PinMode::Output), Ok(()));
+            assert_eq!(op.validate_against_mode(PinMode::InputOutput), Ok(()));
+            assert_eq!(op.validate_against_mode(PinMode::Input), Err(PinError::InvalidValue("Unable to write an input pin")));
+        }
+        for op in &[&temperature, &voltage] {
+            assert_eq!(op.validate_against_mode(PinMode::Input), Ok(()));
+            assert_eq!(op.validate_against_mode(PinMode::InputOutput), Ok(()));
+            assert_eq!(op.validate_against_mode(PinMode::Output), Err(PinError::InvalidValue("Unable to read from an output pin")));
+        }
+    }
+
+    #[test]
+    fn test_pin_operation_explain()
+    {
+        assert_eq!(PinOperation::explain("node1/current/analog/3", "2342"), "ok: node 'node1', pin 3, analog value 2342");
+        assert_eq!(PinOperation::explain("node1/current/toggle/5", ""), "ok: node 'node1', pin 5, toggle value 1");
+        assert_eq!(PinOperation::explain("node1/now/analog/3", "1"), "expected 'current', 'last' or 'timeout' at segment 2, found 'now'");
+        assert_eq!(PinOperation::explain("node1/current/sensor/probe/analog/3", "1"), "expected 'current', 'last' or 'timeout' at segment 4, found 'probe'");
+        assert_eq!(PinOperation::explain("node1/timeout/x/analog/3", "1"), "expected timeout seconds at segment 3, found 'x'");
+        assert_eq!(PinOperation::explain("node1/timeout/analog/3", "1"), "expected timeout seconds before segment 3, found nothing");
+        assert_eq!(PinOperation::explain("node1/current/analog/x", "1"), "expected pin number at segment 4, found 'x'");
+        assert_eq!(PinOperation::explain("3", "1"), "expected value type and pin at segment 1, found '3'");
+        assert_eq!(PinOperation::explain("", "1"), "expected value type and pin before segment 1, found nothing");
+        assert_eq!(PinOperation::explain("analog/3", "1"), "expected 'current', 'last' or 'timeout' before segment 1, found nothing");
+        assert_eq!(PinOperation::explain("current/analog/3", "1"), "expected a node name before segment 1, found nothing");
+        assert_eq!(PinOperation::explain("node1/current/pressure/3", "1"), "unknown value type 'pressure' at segment 3");
+        assert_eq!(PinOperation::explain("node1/current/analog/3", "high"), "invalid payload 'high' for analog: Unable to parse analog value");
+        assert_eq!(PinOperation::explain("node1/timeout/99999999/analog/3", "1"), "value out of range: timeout 99999999s above 2592000s");
+        assert_eq!(PinOperation::explain(&"a/".repeat(20), "1"), "value out of range: topic has more than 16 segments");
+    }
+
+    #[test]
+    fn test_pin_operation_explain_with_options()
+    {
+        let mut registry = ParserRegistry::default();
+        registry.register("pressure", |message| {
+            message.parse::<u16>().map(PinValue::Analog).map_err(|_| PinError::InvalidValue("Unable to parse pressure value"))
+        });
+        let options = ParseOptions { kind_namespaces: vec!["probe".to_string()], max_payload_len: 4, ..ParseOptions::default() };
+        assert_eq!(PinOperation::explain_with_options("node1/current/probe/pressure/3", "1013", &registry, &options),
+            "ok: node 'node1', pin 3, analog value 1013");
+        assert_eq!(PinOperation::explain_with_options("node1/current/sensor/pressure/3", "1013", &registry, &options),
+            "expected 'current', 'last' or 'timeout' at segment 3, found 'sensor'");
+        assert_eq!(PinOperation::explain_with_options("node1/current/probe/pressure/3", "high", &registry, &options),
+            "invalid payload 'high' for pressure: Unable to parse pressure value");
+        assert_eq!(PinOperation::explain_with_options("node1/current/pressure/3", "10130", &registry, &options),
+            "value out of range: payload longer than 4 bytes");
+
+        let options = ParseOptions { max_timeout: Duration::max_value(), ..ParseOptions::default() };
+        let explain = |topic: &str| PinOperationIn::<Local>::explain_with_clock::<FixedClock>(topic, "1", &registry, &options);
+        assert_eq!(explain("node1/timeout/60/analog/3"), "ok: node 'node1', pin 3, analog value 1");
+        assert_eq!(explain(&format!("node1/timeout/{}/analog/3", u32::MAX)), "ok: node 'node1', pin 3, analog value 1");
+        assert_eq!(PinOperationIn::<Local>::explain_with_clock::<FixedClock>("node1/timeout/61/analog/3", "1", &registry,
+            &ParseOptions { max_timeout: Duration::minutes(1), ..ParseOptions::default() }), "value out of range: timeout 61s above 60s");
+        assert_eq!(UtcPinOperation::explain_with_clock::<EndOfTimeClock>("node1/timeout/60/analog/3", "1", &registry, &options),
+            "invalid value: Timeout overflows");
+        assert_eq!(UtcPinOperation::explain_with_clock::<EndOfTimeClock>("node1/current/analog/3", "1", &registry, &options),
+            "ok: node 'node1', pin 3, analog value 1");
+    }
+
+    #[test]
+    fn test_pin_operation_broadcast()
+    {
+        let nodes = vec!["node1".to_string(), "node2".to_string()];
+        let op = PinOperation::from_parts("all/current/digital/5", "1").unwrap();
+        assert_eq!(op.is_broadcast(), true);
+        assert_eq!(op.is_broadcast_to("everyone"), false);
+        let ops = op.expand_broadcast(&nodes);
+        assert_eq!(ops.len(), 2);
+        assert_eq!(ops[0].node, "node1");
+        assert_eq!(ops[1].node, "node2");
+        assert!(ops.iter().all(|o| o.pin_state == op.pin_state));
+
+        let op = PinOperation::from_parts("everyone/current/digital/5", "1").unwrap();
+        assert_eq!(op.is_broadcast(), false);
+        assert_eq!(op.is_broadcast_to("everyone"), true);
+        assert_eq!(op.expand_broadcast(&nodes).len(), 1);
+        let ops = op.expand_broadcast_to("everyone", &nodes);
+        assert_eq!(ops.len(), 2);
+        assert_eq!(ops[0].node, "node1");
+        assert_eq!(ops[1].node, "node2");
+        let op = PinOperation::from_parts("all/current/digital/5", "1").unwrap();
+        assert_eq!(op.expand_broadcast_to("everyone", &nodes)[0].node, "all");
+
+        let op = PinOperation::from_parts("node3/current/digital/5", "1").unwrap();
+        assert_eq!(op.is_broadcast(), false);
+        let ops = op.expand_broadcast(&nodes);
+        assert_eq!(ops.len(), 1);
+        assert_eq!(ops[0].node, "node3");
+    }
+
+    #[test]
+    fn test_pin_operation_from_parts_multi_level_node()
+    {
+        let op = PinOperation::from_parts("building/node1/current/analog/3", "100").unwrap();
+        assert_eq!(op.node, "building/node1");
+        assert_eq!(op.pin_state.pin, 3);
+        assert_eq!(op.pin_state.value, PinValue::Analog(100));
+
+        let op = PinOperation::from_parts("building/floor2/node1/current/analog/3", "100").unwrap();
+        assert_eq!(op.node, "building/floor2/node1");
+        assert_eq!(op.pin_state.until, None);
+
+        let op = PinOperation::from_parts("building/node1/timeout/60/digital/5", "1").unwrap();
+        assert_eq!(op.node, "building/node1");
+        assert!(op.pin_state.until.is_some());
+
+        let op = PinOperation::from_parts("building/floor2/node1/current/timeout/60/digital/5", "1").unwrap();
+        assert_eq!(op.node, "building/floor2/node1");
+        assert!(op.pin_state.until.is_some());
+
+        let op = PinOperation::from_json_message("building/floor2/node1/current/5", r#"{"type":"digital","value":1}"#).unwrap();
+        assert_eq!(op.node, "building/floor2/node1");
+
+        let (topic, _) = TopicBuilder::new().node("building/node1").pin(5).value(&PinValue::Digital(true)).timeout(60).build().unwrap();
+        assert_eq!(PinOperation::from_parts(&topic, "1").unwrap().node, "building/node1");
+    }
+
+    #[test]
+    fn test_pin_operation_from_parts_last()
+    {
+        let dt = Local::now();
+        let last = PinOperation::parse_at("node1/last/analog/3", "100", &ParserRegistry::default(), &ParseOptions::default(), dt).unwrap();
+        let current = PinOperation::parse_at("node1/current/analog/3", "100", &ParserRegistry::default(), &ParseOptions::default(), dt).unwrap();
+        assert_eq!(last.node, "node1");
+        assert_eq!(last.pin_state, current.pin_state);
+        assert_eq!(last.pin_state.until, None);
+
+        let op = PinOperation::from_parts("building/floor2/node1/last/sensor/temperature/5", "21.5").unwrap();
+        assert_eq!(op.node, "building/floor2/node1");
+        assert_eq!(op.pin_state.value, PinValue::Temperature(Temperature::new(21.5_f32)));
+
+        assert_eq!(PinOperation::from_parts("last/analog/3", "1").unwrap_err(), PinError::InvalidTopic("Unknown node"));
+        assert_eq!(PinOperation::from_parts("node1/last/probe/analog/3", "1").unwrap_err(), PinError::InvalidTopic("Unexpected segment before value type"));
+    }
+
+    #[test]
+    fn test_pin_operation_from_parts_malformed()
+    {
+        assert_eq!(PinOperation::from_parts("", "1").unwrap_err(), PinError::InvalidTopic("Expected value type and pin"));
+        assert_eq!(PinOperation::from_parts("3", "1").unwrap_err(), PinError::InvalidTopic("Expected value type and pin"));
+        assert_eq!(PinOperation::from_parts("/3", "1").unwrap_err(), PinError::InvalidTopic("Expected value type and pin"));
+        assert_eq!(PinOperation::from_parts("analog/3", "1").unwrap_err(), PinError::InvalidTopic("Expected current, last or timeout"));
+        assert_eq!(PinOperation::from_parts("current/analog/3", "1").unwrap_err(), PinError::InvalidTopic("Unknown node"));
+        assert_eq!(PinOperation::from_parts("/current/analog/3", "1").unwrap_err(), PinError::InvalidTopic("Unknown node"));
+        assert_eq!(PinOperation::from_parts("node1/now/analog/3", "1").unwrap_err(), PinError::InvalidTopic("Expected current, last or timeout"));
+        assert_eq!(PinOperation::from_parts("timeout/3600/analog/3", "1").unwrap_err(), PinError::InvalidTopic("Unknown node after timeout"));
+        assert_eq!(PinOperation::from_parts("current/timeout/3600/analog/3", "1").unwrap_err(), PinError::InvalidTopic("Unknown node after timeout"));
+        assert_eq!(PinOperation::from_parts("node1/timeout/analog/3", "1").unwrap_err(), PinError::InvalidTopic("Expected current, last or timeout"));
+        assert_eq!(PinOperation::from_parts("node1/timeout/x/analog/3", "1").unwrap_err(), PinError::InvalidValue("Unable to parse timeout"));
+        assert_eq!(PinOperation::from_parts("node1/current/analog/", "1").unwrap_err(), PinError::InvalidTopic("Unable to parse integer"));
+        assert_eq!(PinOperation::from_parts("node1/current/probe/temperature/5", "1").unwrap_err(), PinError::InvalidTopic("Unexpected segment before value type"));
+        assert_eq!(PinOperation::from_parts("node1/current/sensor/sensor/temperature/5", "1").unwrap_err(), PinError::InvalidTopic("Unexpected segment before value type"));
+    }
+
+    #[test]
+    fn test_pin_operation_from_parts_empty_segments()
+    {
+        let dt = Local::now();
+        let clean = PinOperation::parse_at("building/node1/timeout/60/analog/3", "1", &ParserRegistry::default(), &ParseOptions::default(), dt).unwrap();
+        for topic in &["/building/node1/timeout/60/analog/3", "building/node1/timeout/60/analog/3/", "building//node1/timeout//60/analog/3", "//building/node1/timeout/60/analog/3//"] {
+            let op = PinOperation::parse_at(topic, "1", &ParserRegistry::default(), &ParseOptions::default(), dt).unwrap();
+            assert_eq!(op.node, clean.node);
+            assert_eq!(op.pin_state, clean.pin_state);
+        }
+        assert_eq!(PinOperation::from_json_message("/node1//current/5/", r#"{"type":"digital","value":1}"#).unwrap().node, "node1");
+        assert_eq!(PinOperation::from_parts("//current/analog/3", "1").unwrap_err(), PinError::InvalidTopic("Unknown node"));
+        assert_eq!(PinOperation::from_parts("node1///analog/3", "1").unwrap_err(), PinError::InvalidTopic("Expected current, last or timeout"));
+        assert_eq!(PinOperation::explain("/node1/now/analog/3", "1"), "expected 'current', 'last' or 'timeout' at segment 2, found 'now'");
+    }
+
+    #[test]
+    fn test_pin_operation_from_parts_size_limits()
+    {
+        let topic = format!("{}node1/current/analog/3", "a/".repeat(10_000));
+        assert_eq!(PinOperation::from_parts(&topic, "1").unwrap_err(), PinError::OutOfRange("topic has more than 16 segments".to_string()));
+        assert_eq!(PinOperation::from_json_message(&format!("{}node1/current/3", "a/".repeat(10_000)), "{}").unwrap_err(),
+            PinError::OutOfRange("topic has more than 16 segments".to_string()));
+        let topic = format!("{}node1/current/analog/3", "a/".repeat(12));
+        assert_eq!(PinOperation::from_parts(&topic, "1").unwrap().pin_state.value, PinValue::Analog(1));
+
+        let payload = "1".repeat(100_000);
+        assert_eq!(PinOperation::from_parts("node1/current/temperature/3", &payload).unwrap_err(), PinError::OutOfRange("payload longer than 64 bytes".to_string()));
+
+        let options = ParseOptions { max_segments: 4, max_payload_len: 100_000, ..ParseOptions::default() };
+        assert_eq!(PinOperation::from_parts_with_options("node1/current/analog/3", "1", &ParserRegistry::default(), &options).unwrap().node, "node1");
+        assert_eq!(PinOperation::from_parts_with_options("b/node1/current/analog/3", "1", &ParserRegistry::default(), &options).unwrap_err(),
+            PinError::OutOfRange("topic has more than 4 segments".to_string()));
+        assert_eq!(PinOperation::from_parts_with_options("node1/current/temperature/3", &payload, &ParserRegistry::default(), &options).is_ok(), true);
+
+        let json = r#"{ "type": "temperature", "value": "21.50", "timeout": 3600, "source": "greenhouse/north" }"#;
+        assert!(json.len() > ParseOptions::default().max_payload_len);
+        let op = PinOperation::from_json_message("node1/current/5", json).unwrap();
+        assert_eq!(op.pin_state.value, PinValue::Temperature(Temperature::new(21.5_f32)));
+        assert!(op.pin_state.until.is_some());
+        let json = format!(r#"{{"type":"analog","value":1,"note":"{}"}}"#, "x".repeat(2000));
+        assert_eq!(PinOperation::from_json_message("node1/current/5", &json).unwrap_err(), PinError::OutOfRange("json payload longer than 1024 bytes".to_string()));
+    }
+
+    #[test]
+    fn test_pin_operation_from_parts_kind_namespace()
+    {
+        for (namespaced, plain) in &[
+            ("node1/current/sensor/temperature/5", "node1/current/temperature/5"),
+            ("node1/current/actuator/digital/5", "node1/current/digital/5"),
+            ("node1/timeout/60/actuator/analog/5", "node1/timeout/60/analog/5"),
+            ("building/node1/current/timeout/60/sensor/temperature/5", "building/node1/current/timeout/60/temperature/5"),
+        ] {
+            let dt = Local::now();
+            let a = PinOperation::parse_at(namespaced, "1", &ParserRegistry::default(), &ParseOptions::default(), dt).unwrap();
+            let b = PinOperation::parse_at(plain, "1", &ParserRegistry::default(), &ParseOptions::default(), dt).unwrap();
+            assert_eq!(a.node, b.node);
+            assert_eq!(a.pin_state, b.pin_state);
+            assert_eq!(a.toggle, b.toggle);
+        }
+
+        let options = ParseOptions { kind_namespaces: vec!["probe".to_string()], ..ParseOptions::default() };
+        assert_eq!(PinOperation::from_parts_with_options("node1/current/probe/temperature/5", "20", &ParserRegistry::default(), &options).unwrap().pin_state.value,
+            PinValue::Temperature(Temperature::new(20_f32)));
+        assert_eq!(PinOperation::from_parts_with_options("node1/current/sensor/temperature/5", "20", &ParserRegistry::default(), &options).unwrap_err(),
+            PinError::InvalidTopic("Unexpected segment before value type"));
+    }
+
+    #[test]
+    fn test_pin_operation_resolve_toggle()
+    {
+        let registry = ParserRegistry::default();
+        let op = PinOperation::from_parts_with_registry("node1/current/toggle/5", "", &registry).unwrap();
+        assert_eq!(op.toggle, true);
+        assert_eq!(op.node, "node1");
+
+        let mut col = PinCollection::default();
+        assert_eq!(col.last_digital_value(), None);
+        assert_eq!(op.resolve_toggle(&col).value, PinValue::Digital(true));
+
+        col.push(&PinState::now(5, PinValue::Digital(true)));
+        col.push(&PinState::now(5, PinValue::Analog(0)));
+        let state = op.resolve_toggle(&col);
+        assert_eq!(state.value, PinValue::Digital(false));
+        assert_eq!(state.pin, 5);
+
+        col.push(&state);
+        assert_eq!(op.resolve_toggle(&col).value, PinValue::Digital(true));
+
+        let op = PinOperation::from_parts_with_registry("node1/timeout/60/toggle/5", "", &registry).unwrap();
+        assert!(op.resolve_toggle(&col).until.is_some());
+
+        let op = PinOperation::from_parts_with_registry("node1/current/digital/5", "0", &registry).unwrap();
+        assert_eq!(op.toggle, false);
+        assert_eq!(op.resolve_toggle(&col).value, PinValue::Digital(false));
+    }
+
+    #[test]
+    fn test_pin_operation_timeout_limits()
+    {
+        let registry = ParserRegistry::default();
+        assert_eq!(PinOperation::from_parts_with_registry("node1/timeout/4294967295/digital/5", "1", &registry).unwrap_err(),
+            PinError::OutOfRange("timeout 4294967295s above 2592000s".to_string()));
+        assert_eq!(PinOperation::from_parts_with_registry("node1/timeout/-1/digital/5", "1", &registry).unwrap_err(),
+            PinError::InvalidValue("Unable to parse timeout"));
+        assert_eq!(PinOperation::from_json_message("node1/current/5", r#"{"type":"digital","value":1,"timeout":4294967295}"#).is_err(), true);
+        assert!(PinOperation::from_parts_with_registry("node1/timeout/2592000/digital/5", "1", &registry).is_ok());
+
+        let options = ParseOptions { max_timeout: Duration::hours(1), ..ParseOptions::default() };
+        assert!(PinOperation::from_parts_with_options("node1/timeout/3600/digital/5", "1", &registry, &options).is_ok());
+        assert_eq!(PinOperation::from_parts_with_options("node1/timeout/3601/digital/5", "1", &registry, &options).unwrap_err(),
+            PinError::OutOfRange("timeout 3601s above 3600s".to_string()));
+
+        let options = ParseOptions { max_timeout: Duration::seconds(i64::MAX / 1000), ..ParseOptions::default() };
+        let dt = Utc.timestamp(8_210_266_876_799, 0);
+        assert_eq!(UtcPinOperation::parse_at("node1/timeout/4294967295/digital/5", "1", &registry, &options, dt).unwrap_err(),
+            PinError::InvalidValue("Timeout overflows"));
+    }
+
+    #[test]
+    fn test_pin_operation_from_parts_with_timestamp()
+    {
+        let epoch = 1_500_000_000_i64;
+        let op = PinOperation::from_parts_with_timestamp("node1/current/analog/3", "100", epoch).unwrap();
+        assert_eq!(op.pin_state.dt.timestamp(), epoch);
+        assert_eq!(op.pin_state.until, None);
+
+        let op = UtcPinOperation::from_parts_with_timestamp("node1/timeout/3600/digital/5", "1", epoch).unwrap();
+        assert_eq!(op.pin_state.dt, Utc.timestamp(epoch, 0));
+        assert_eq!(op.pin_state.until, Some(Utc.timestamp(epoch + 3600, 0)));
+        assert_eq!(op.node, "node1");
+
+        assert_eq!(PinOperation::from_parts_with_timestamp("node1/current/analog/3", "100", i64::MAX).unwrap_err(),
+            PinError::InvalidValue("Unable to convert timestamp"));
+    }
+
+    #[test]
+    fn test_utc_pin_collection()
+    {
+        let op = UtcPinOperation::from_parts_with_registry("node1/timeout/60/temperature/3", "20", &ParserRegistry::default()).unwrap();
+        let until: DateTime<Utc> = op.pin_state.until.unwrap();
+        assert!(until > Utc::now() + Duration::seconds(50));
+
+        let mut col = UtcPinCollection::default();
+        let since = Utc::now() - Duration::seconds(100);
+        col.push(&UtcPinState::new(3, PinValue::Temperature(Temperature::new(0_f32)), since - Duration::seconds(1), None));
+        col.push(&op.pin_state);
+        col.push(&UtcPinState::now(3, PinValue::Temperature(Temperature::new(10_f32))));
+        assert_eq!(col.get_average_temperature(&since), Some(Temperature::new(15_f32)));
+        assert_eq!(col.is_stale(Duration::seconds(60)), false);
+
+        // timestamps are instants, filtering with another time zone yields the same window
+        let local = PinCollection::from_states(&[PinState::new(3, PinValue::Temperature(Temperature::new(10_f32)), Local::now(), None)]);
+        assert_eq!(local.get_average_temperature(&since.with_timezone(&Local)), Some(Temperature::new(10_f32)));
+    }
+
+    #[test]
+    fn test_filter_invalid()
+    {
+        let state = PinState {pin: 3_u8, value: PinValue::Digital(true), dt: Local::now(), until: None};
+        let ops = vec![
+            PinOperation::new(state.clone(), "node1".to_string()),
+            PinOperation::new(PinState { pin: 20, ..state.clone() }, "node1".to_string()),
+            PinOperation::new(state.clone(), "".to_string()),
+            PinOperation::new(PinState { pin: 13, ..state.clone() }, "node2".to_string()),
+        ];
+        assert_eq!(filter_invalid(&ops, 13), vec![(1, PinError::InvalidPin(20)), (2, PinError::InvalidTopic("Empty node"))]);
+        assert_eq!(filter_invalid(&ops[..1], 13), vec![]);
+        assert_eq!(filter_invalid(&ops[..0], 13), vec![]);
+    }
+
+    #[test]
+    fn test_pin_collection_is_on_off()
+    {
+        let mut col = PinCollection::default();
+        assert_eq!(col.is_on(), false);
+
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(20.5_f32)), dt: Local::now(), until: None});
+        assert_eq!(col.is_on(), false);
+        assert_eq!(col.is_off(), false);
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Analog(123_u16), dt: Local::now(), until: None});
+        assert_eq!(col.is_on(), true);
+        assert_eq!(col.is_off(), false);
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Analog(0_u16), dt: Local::now(), until: None});
+        assert_eq!(col.is_on(), false);
+        assert_eq!(col.is_off(), true);
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Analog(123_u16), dt: Local::now(), until: Some(Local::now() + Duration::seconds(3))});
+        assert_eq!(col.is_on(), true);
+        assert_eq!(col.is_off(), false);
+
+        // turn off first
+        col.push(&PinState {pin: 1_u8, value: PinValue::Analog(0_u16), dt: Local::now(), until: None});
+        col.push(&PinState {pin: 1_u8, value: PinValue::Analog(123_u16), dt: Local::now(), until: Some(Local::now() - Duration::seconds(3))});
+        assert_eq!(col.is_on(), false);
+        assert_eq!(col.is_off(), false);
+    }
+
+
+    #[test]
+    fn test_pin_collection_get_average_temperature()
+    {
+        let mut col = PinCollection::default();
+        let since = Local::now() - Duration::seconds(100);
+        assert_eq!(col.get_average_temperature(&since), None);
+
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(20_f32)), dt: Local::now(), until: None});
+        assert_eq!(col.get_average_temperature(&since).unwrap(), Temperature::new(20_f32));
+
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(10_f32)), dt: Local::now(), until: None});
+        assert_eq!(col.get_average_temperature(&since).unwrap(), Temperature::new(15_f32));
+
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(18_f32)), dt: Local::now(), until: None});
+        assert_eq!(col.get_average_temperature(&since).unwrap(), Temperature::new(16_f32));
+
+        assert_eq!(col.get_average_temperature(&(since + Duration::seconds(200))), None);
+    }
+
+    #[test]
+    fn test_pin_collection_get_average_temperature_rounded()
+    {
+        let mut col = PinCollection::default();
+        let since = Local::now() - Duration::seconds(100);
+        assert_eq!(col.get_average_temperature_rounded(&since, 1), None);
+
+        for value in &[16_f32, 17_f32, 16.998_f32] {
+            col.push(&PinState::now(3, PinValue::Temperature(Temperature::new(*value))));
+        }
+        assert_eq!(col.get_average_temperature_rounded(&since, 0), Some(17_f32));
+        assert_eq!(col.get_average_temperature_rounded(&since, 1), Some(16.7_f32));
+        assert_eq!(col.get_average_temperature_rounded(&since, 2), Some(16.67_f32));
+        assert_eq!(col.get_average_temperature_rounded(&since, 3), Some(16.666_f32));
+        assert_eq!(col.get_average_temperature_rounded(&since, 9), Some(16.666_f32));
+    }
+
+    #[test]
+    fn test_pin_collection_get_average_temperature_min_samples()
+    {
+        let mut col = PinCollection::default();
+        let since = Local::now() - Duration::seconds(100);
+        assert_eq!(col.get_average_temperature_min_samples(&since, 3), None);
+
+        col.push(&PinState::now(3, PinValue::Temperature(Temperature::new(20_f32))));
+        col.push(&PinState::now(1, PinValue::Digital(true)));
+        col.push(&PinState::now(1, PinValue::Digital(false)));
+        assert_eq!(col.get_average_temperature_min_samples(&since, 3), None);
+        assert_eq!(col.get_average_temperature_min_samples(&since, 1), Some(Temperature::new(20_f32)));
+
+        col.push(&PinState::now(3, PinValue::Temperature(Temperature::new(22_f32))));
+        col.push(&PinState::now(3, PinValue::Temperature(Temperature::new(24_f32))));
+        assert_eq!(col.get_average_temperature_min_samples(&since, 3), Some(Temperature::new(22_f32)));
+        assert_eq!(col.get_average_temperature_min_samples(&since, 0), Some(Temperature::new(22_f32)));
+    }
+
+    #[test]
+    fn test_pin_collection_get_average_analog()
+    {
+        let mut col = PinCollection::default();
+        let since = Local::now() - Duration::seconds(100);
+        assert_eq!(col.get_average_analog(&since), None);
+
+        col.push(&PinState {pin: 3_u8, value: PinValue::Analog(100), dt: Local::now(), until: None});
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: Local::now(), until: None});
+        col.push(&PinState {pin: 3_u8, value: PinValue::Analog(300), dt: Local::now(), until: None});
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(false), dt: Local::now(), until: None});
+        col.push(&PinState {pin: 4_u8, value: PinValue::Temperature(Temperature::new(20_f32)), dt: Local::now(), until: None});
+        assert_eq!(col.get_average_analog(&since), Some(200_f32));
+
+        assert_eq!(col.get_average_analog(&(since + Duration::seconds(200))), None);
+    }
+
+    #[test]
+    fn test_pin_collection_voltage()
+    {
+        let mut col = PinCollection::default();
+        let since = Local::now() - Duration::seconds(100);
+        assert_eq!(col.get_average_voltage(&since), None);
+
+        col.push(&PinOperation::from_parts("node1/current/voltage/3", "3.30").unwrap().pin_state);
+        assert_eq!(col.is_on(), true);
+        col.push(&PinState::now(3, PinValue::Voltage(1.1_f32)));
+        col.push(&PinState::now(3, PinValue::Analog(500)));
+        assert!((col.get_average_voltage(&since).unwrap() - 2.2_f32).abs() < 0.0001);
+        assert_eq!(col.get_average_analog(&since), Some(500_f32));
+
+        col.push(&PinState::now(3, PinValue::Voltage(0_f32)));
+        assert_eq!(col.is_off(), true);
+        assert_eq!(col.change_count(&since), 3);
+    }
+
+    #[derive(Debug)]
+    struct FixedClock;
+
+    impl Clock<Local> for FixedClock
+    {
+        fn now() -> DateTime<Local>
+        {
+            Local.timestamp_opt(1_600_000_000, 0).unwrap()
+        }
+    }
+
+    struct EndOfTimeClock;
+
+    impl Clock<Utc> for EndOfTimeClock
+    {
+        fn now() -> DateTime<Utc>
+        {
+            chrono::MAX_DATE.and_hms(23, 59, 59)
+        }
+    }
+
+    #[test]
+    fn test_pin_collection_with_clock()
+    {
+        let now = FixedClock::now();
+        let mut col: PinCollectionIn<Local, FixedClock> = PinCollectionIn::default();
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now - Duration::seconds(10), until: Some(now + Duration::seconds(60))});
+        assert_eq!(col.is_on(), true);
+        assert_eq!(col.active_until(), Some(now + Duration::seconds(60)));
+        assert_eq!(col.time_since_last_change(), Some(Duration::seconds(10)));
+        assert_eq!(col.is_stale(Duration::seconds(10)), false);
+        assert_eq!(col.is_stale(Duration::seconds(9)), true);
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(false), dt: now - Duration::seconds(5), until: Some(now - Duration::seconds(1))});
+        assert_eq!(col.is_off(), false);
+
+        let op = PinOperation::from_parts_with_clock::<FixedClock>("node1/timeout/60/digital/5", "1", &ParserRegistry::default(), &ParseOptions::default()).unwrap();
+        assert_eq!(op.pin_state.dt, now);
+        assert_eq!(op.pin_state.until, Some(now + Duration::seconds(60)));
+
+        let mut registry: PinRegistryIn<Local, FixedClock> = PinRegistryIn::default();
+        registry.push_operation(&op);
+        assert_eq!(registry.collection(5).unwrap().active_until(), Some(now + Duration::seconds(60)));
+    }
+
+    #[test]
+    fn test_pin_collection_with_cooldown()
+    {
+        let now = Local::now();
+        let mut col = PinCollection::default().with_cooldown(Duration::seconds(60));
+        assert_eq!(col.can_turn_on(), true);
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now - Duration::seconds(20), until: None});
+        assert_eq!(col.can_turn_on(), true);
+        assert_eq!(col.is_on(), true);
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(false), dt: now - Duration::seconds(10), until: None});
+        assert_eq!(col.can_turn_on(), false);
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now - Duration::seconds(5), until: None});
+        assert_eq!(col.can_turn_on(), false);
+        assert_eq!(col.is_on(), false);
+
+        // cooldown already elapsed since the off change
+        let col = col.with_cooldown(Duration::seconds(10));
+        assert_eq!(col.can_turn_on(), true);
+        assert_eq!(col.is_on(), true);
+        // an off state known only from the seeded baseline still starts the cooldown
+        let mut col = PinCollection::default().with_cooldown(Duration::seconds(60));
+        col.seed(&PinState {pin: 1_u8, value: PinValue::Digital(false), dt: now - Duration::seconds(10), until: None});
+        assert_eq!(col.can_turn_on(), false);
+        let col = col.with_cooldown(Duration::seconds(10));
+        assert_eq!(col.can_turn_on(), true);
+    }
+
+    #[test]
+    fn test_pin_collection_push_forced()
+    {
+        let mut col = PinCollection::default();
+        let since = Local::now() - Duration::seconds(100);
+        for _ in 0..3 {
+            col.push_forced(&PinState::now(1, PinValue::Digital(true)));
+        }
+        assert_eq!(col.change_count(&since), 3);
+        assert_eq!(col.version(), 3);
+        assert_eq!(col.has_sufficient_samples(&since, 3), true);
+
+        col.push(&PinState::now(1, PinValue::Digital(true)));
+        assert_eq!(col.change_count(&since), 3);
+    }
+
+    #[test]
+    fn test_pin_collection_version()
+    {
+        let mut col = PinCollection::default();
+        assert_eq!(col.version(), 0);
+
+        col.push(&PinState::now(1, PinValue::Digital(true)));
+        let cached = col.version();
+        assert_eq!(cached, 1);
+
+        col.push(&PinState::now(1, PinValue::Digital(true)));
+        col.push(&PinState::now(3, PinValue::Temperature(Temperature::new(20_f32))));
+        assert_eq!(col.version(), cached);
+
+        col.push(&PinState::now(1, PinValue::Digital(false)));
+        assert_eq!(col.version(), cached + 1);
+    }
+
+    #[test]
+    fn test_pin_collection_into_changes()
+    {
+        let mut col = PinCollection::default();
+        for value in &[true, true, false, false, true] {
+            col.push(&PinState::now(1, PinValue::Digital(*value)));
+        }
+        col.push(&PinState::now(3, PinValue::Temperature(Temperature::new(20_f32))));
+        let changes: Vec<PinValue> = col.into_iter().map(|state| state.value).collect();
+        assert_eq!(changes, vec![PinValue::Digital(true), PinValue::Digital(false), PinValue::Digital(true)]);
+
+        let mut col = PinCollection::default();
+        col.push(&PinState::now(4, PinValue::Analog(0)));
+        col.push(&PinState::now(4, PinValue::Analog(300)));
+        assert_eq!(col.into_changes().len(), 2);
+    }
+
+    #[test]
+    fn test_pin_collection_seed()
+    {
+        let mut col = PinCollection::default();
+        let since = Local::now() - Duration::seconds(100);
+        col.seed(&PinState::now(1, PinValue::Digital(true)));
+        col.seed(&PinState::now(2, PinValue::Analog(300)));
+        assert_eq!(col.get_last_changed_value(), Some(PinValue::Analog(300)));
+        assert_eq!(col.change_count(&since), 0);
+        assert_eq!(col.has_sufficient_samples(&since, 2), true);
+
+        col.push(&PinState::now(1, PinValue::Digital(true)));
+        col.push(&PinState::now(2, PinValue::Analog(500)));
+        assert_eq!(col.change_count(&since), 0);
+
+        col.push(&PinState::now(1, PinValue::Digital(false)));
+        assert_eq!(col.get_last_changed_value(), Some(PinValue::Digital(false)));
+        assert_eq!(col.change_count(&since), 1);
+
+        col.clear();
+        col.push(&PinState::now(1, PinValue::Digital(true)));
+        assert_eq!(col.change_count(&since), 1);
+    }
+
+    #[test]
+    fn test_pin_collection_seed_fallback()
+    {
+        let now = Local::now();
+        let mut col = PinCollection::default();
+        assert_eq!(col.is_on(), false);
+
+        col.seed(&PinState::new(1, PinValue::Digital(true), now - Duration::seconds(60), None));
+        assert_eq!(col.is_on(), true);
+        assert_eq!(col.get_last_changed().unwrap().dt, now - Duration::seconds(60));
+
+        // seeding the same pin and kind again replaces the baseline instead of growing it
+        for i in 0..100 {
+            col.seed(&PinState::new(1, PinValue::Digital(i % 2 == 0), now - Duration::seconds(50), None));
+        }
+        assert_eq!(col.baseline.len(), 1);
+        assert_eq!(col.is_off(), true);
+        assert_eq!(col.get_last_changed_value(), Some(PinValue::Digital(false)));
+
+        col.seed(&PinState::new(2, PinValue::Analog(300), now - Duration::seconds(70), None));
+        assert_eq!(col.baseline.len(), 2);
+        assert_eq!(col.get_last_changed_value(), Some(PinValue::Digital(false)));
+
+        col.push(&PinState::new(1, PinValue::Digital(true), now - Duration::seconds(10), None));
+        assert_eq!(col.is_on(), true);
+        assert_eq!(col.get_last_changed().unwrap().dt, now - Duration::seconds(10));
+    }
+
+    #[test]
+    fn test_pin_collection_time_since_last_change()
+    {
+        let mut col = PinCollection::default();
+        assert_eq!(col.time_since_last_change(), None);
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: Local::now() - Duration::seconds(180), until: None});
+        col.push(&PinState::now(3, PinValue::Temperature(Temperature::new(20_f32))));
+        let elapsed = col.time_since_last_change().unwrap();
+        assert!(elapsed >= Duration::seconds(180));
+        assert!(elapsed < Duration::seconds(190));
+    }
+
+    #[test]
+    fn test_pin_collection_last_n_states()
+    {
+        let mut col = PinCollection::default();
+        assert_eq!(col.last_n_states(10).len(), 0);
+        assert_eq!(col.last_n_temperatures(10).len(), 0);
+
+        for i in 0..15 {
+            col.push(&PinState::now(3, PinValue::Temperature(Temperature::new(i as f32))));
+            col.push(&PinState::now(4, PinValue::Analog(i)));
+        }
+        let states = col.last_n_states(3);
+        assert_eq!(states.len(), 3);
+        assert_eq!(states[0].value, PinValue::Analog(14));
+        assert_eq!(states[1].value, PinValue::Temperature(Temperature::new(14_f32)));
+        assert_eq!(states[2].value, PinValue::Analog(13));
+        assert_eq!(col.last_n_states(50).len(), 30);
+
+        assert_eq!(col.last_n_temperatures(3), vec![14_f32, 13_f32, 12_f32]);
+        assert_eq!(col.last_n_temperatures(50).len(), 15);
+    }
+
+    #[test]
+    fn test_pin_collection_with_analog_level_tracking()
+    {
+        let since = Local::now() - Duration::seconds(100);
+        let mut plain = PinCollection::default();
+        let mut col = PinCollection::default().with_analog_level_tracking(100);
+        for v in &[200_u16, 250, 299, 300, 800, 850, 0] {
+            plain.push(&PinState::now(4, PinValue::Analog(*v)));
+            col.push(&PinState::now(4, PinValue::Analog(*v)));
+        }
+        assert_eq!(plain.change_count(&since), 2);
+        let changes: Vec<PinValue> = col.changes_since(&since).into_iter().map(|state| state.value).collect();
+        assert_eq!(changes, vec![PinValue::Analog(0), PinValue::Analog(800), PinValue::Analog(300), PinValue::Analog(200)]);
+
+        let mut off = PinCollection::default().with_analog_level_tracking(0);
+        off.push(&PinState::now(4, PinValue::Analog(200)));
+        off.push(&PinState::now(4, PinValue::Analog(800)));
+        assert_eq!(off.change_count(&since), 1);
+    }
+
+    #[test]
+    fn test_pin_collection_with_baseline_deviation()
+    {
+        let since = Local::now() - Duration::seconds(100);
+        let mut plain = PinCollection::default();
+        let mut col = PinCollection::default().with_baseline_deviation(50);
+        let ramp = (0..16).map(|i| 500 + i * 10).chain([650, 650, 650].iter().copied());
+        for v in ramp {
+            plain.push(&PinState::now(4, PinValue::Analog(v)));
+            col.push(&PinState::now(4, PinValue::Analog(v)));
+        }
+        assert_eq!(plain.change_count(&since), 1);
+        let changes: Vec<PinValue> = col.changes_since(&since).into_iter().map(|state| state.value).collect();
+        assert_eq!(changes, [650, 640, 630, 620, 610, 600, 500].iter().map(|v| PinValue::Analog(*v)).collect::<Vec<PinValue>>());
+
+        let mut off = PinCollection::default().with_baseline_deviation(0);
+        off.push(&PinState::now(4, PinValue::Analog(200)));
+        off.push(&PinState::now(4, PinValue::Analog(800)));
+        assert_eq!(off.change_count(&since), 1);
+    }
+
+    #[test]
+    fn test_pin_collection_extend_timeout()
+    {
+        let now = FixedClock::now();
+        let mut col: PinCollectionIn<Local, FixedClock> = PinCollectionIn::default();
+        assert_eq!(col.extend_timeout(Duration::minutes(30)), false);
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now, until: None});
+        assert_eq!(col.extend_timeout(Duration::minutes(30)), false);
+        assert_eq!(col.active_until(), None);
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(false), dt: now, until: Some(now + Duration::minutes(10))});
+        assert_eq!(col.extend_timeout(Duration::minutes(30)), false);
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now, until: Some(now + Duration::minutes(10))});
+        assert_eq!(col.extend_timeout(Duration::minutes(30)), true);
+        assert_eq!(col.active_until(), Some(now + Duration::minutes(40)));
+        assert_eq!(col.last_n_states(1)[0].until, Some(now + Duration::minutes(40)));
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(false), dt: now, until: None});
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now - Duration::minutes(20), until: Some(now - Duration::minutes(10))});
+        assert_eq!(col.extend_timeout(Duration::minutes(30)), false);
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(false), dt: now, until: None});
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now, until: Some(now + Duration::days(1))});
+        assert_eq!(col.extend_timeout(Duration::max_value()), false);
+        assert_eq!(col.active_until(), Some(now + Duration::days(1)));
+
+        // a timed on baseline is extended while no change is recorded
+        let mut col: PinCollectionIn<Local, FixedClock> = PinCollectionIn::default();
+        col.seed(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now, until: Some(now + Duration::minutes(10))});
+        assert_eq!(col.extend_timeout(Duration::minutes(30)), true);
+        assert_eq!(col.active_until(), Some(now + Duration::minutes(40)));
+        assert_eq!(col.last_n_states(1)[0].until, Some(now + Duration::minutes(40)));
+    }
+
+    #[test]
+    fn test_pin_collection_get_effective_value()
+    {
+        let now = FixedClock::now();
+        let mut col: PinCollectionIn<Local, FixedClock> = PinCollectionIn::default();
+        assert_eq!(col.get_effective_value(), None);
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now - Duration::seconds(60), until: Some(now - Duration::seconds(1))});
+        assert_eq!(col.get_last_changed_value(), Some(PinValue::Digital(true)));
+        assert_eq!(col.get_effective_value(), Some(PinValue::Digital(false)));
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(false), dt: now - Duration::seconds(30), until: None});
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now - Duration::seconds(10), until: Some(now + Duration::seconds(10))});
+        assert_eq!(col.get_effective_value(), Some(PinValue::Digital(true)));
+
+        col.push(&PinState {pin: 4_u8, value: PinValue::Analog(700), dt: now - Duration::seconds(5), until: Some(now - Duration::seconds(1))});
+        assert_eq!(col.get_effective_value(), Some(PinValue::Analog(0)));
+        col.push(&PinState {pin: 4_u8, value: PinValue::Analog(0), dt: now, until: None});
+        assert_eq!(col.get_effective_value(), Some(PinValue::Analog(0)));
+    }
+
+    #[test]
+    fn test_pin_collection_is_temporary()
+    {
+        let now = FixedClock::now();
+        let mut col: PinCollectionIn<Local, FixedClock> = PinCollectionIn::default();
+        assert_eq!(col.is_temporary(), false);
+        assert_eq!(col.until_remaining(), None);
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now, until: None});
+        assert_eq!(col.is_temporary(), false);
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(false), dt: now, until: None});
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now, until: Some(now + Duration::seconds(90))});
+        assert_eq!(col.is_temporary(), true);
+        assert_eq!(col.until_remaining(), Some(Duration::seconds(90)));
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(false), dt: now, until: Some(now - Duration::seconds(1))});
+        assert_eq!(col.is_temporary(), false);
+        assert_eq!(col.until_remaining(), None);
+    }
+
+    #[test]
+    fn test_pin_collection_active_until()
+    {
+        let mut col = PinCollection::default();
+        let now = Local::now();
+        assert_eq!(col.active_until(), None);
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now, until: None});
+        assert_eq!(col.active_until(), None);
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(false), dt: now, until: Some(now + Duration::seconds(60))});
+        assert_eq!(col.active_until(), None);
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now, until: Some(now + Duration::seconds(60))});
+        assert_eq!(col.active_until(), Some(now + Duration::seconds(60)));
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(false), dt: now, until: None});
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now - Duration::seconds(60), until: Some(now - Duration::seconds(1))});
+        assert_eq!(col.active_until(), None);
+    }
+
+    #[test]
+    fn test_pin_registry()
+    {
+        let mut registry = PinRegistry::default();
+        assert_eq!(registry.collection(3).is_none(), true);
+
+        registry.push(&PinState::now(3, PinValue::Digital(true)));
+        registry.push(&PinState::now(8, PinValue::Analog(100)));
+        registry.push(&PinState::now(3, PinValue::Digital(false)));
+        assert_eq!(registry.collection(3).unwrap().get_last_changed_value(), Some(PinValue::Digital(false)));
+        assert_eq!(registry.collection(8).unwrap().get_last_changed_value(), Some(PinValue::Analog(100)));
+
+        registry.push_operation(&PinOperation::from_parts("node1/current/toggle/3", "").unwrap());
+        assert_eq!(registry.collection(3).unwrap().is_on(), true);
+        assert_eq!(registry.collection(8).unwrap().is_on(), true);
+    }
+
+    #[test]
+    fn test_pin_registry_pins()
+    {
+        let mut registry = PinRegistry::default();
+        assert_eq!(registry.pins(), Vec::<u8>::new());
+
+        registry.push(&PinState::now(8, PinValue::Analog(100)));
+        registry.push(&PinState::now(3, PinValue::Digital(true)));
+        registry.push(&PinState::now(3, PinValue::Digital(false)));
+        assert_eq!(registry.pins(), vec![3, 8]);
+        assert_eq!(registry.contains_pin(3), true);
+        assert_eq!(registry.contains_pin(5), false);
+    }
+
+    #[test]
+    fn test_pin_registry_apply()
+    {
+        let mut registry = PinRegistry::default();
+        let op = PinOperation::from_parts("node1/current/digital/5", "1").unwrap();
+        assert_eq!(registry.apply(&op), ApplyResult::new(5, true, None, Some(PinValue::Digital(true))));
+        assert_eq!(registry.apply(&op), ApplyResult::new(5, false, Some(PinValue::Digital(true)), Some(PinValue::Digital(true))));
+
+        let op = PinOperation::from_parts("node1/current/toggle/5", "").unwrap();
+        assert_eq!(registry.apply(&op), ApplyResult::new(5, true, Some(PinValue::Digital(true)), Some(PinValue::Digital(false))));
+    }
+
+    #[test]
+    fn test_ingest_messages()
+    {
+        let lines = [
+            ("node1/current/digital/5", "1"),
+            ("node1/current/temperature/3", "20.5"),
+            ("node2/timeout/60/analog/8", "512"),
+            ("garbage", "1"),
+            ("node1/current/digital/5", "0"),
+            ("node2/current/timeout/60/digital/2", "1"),
+            ("node2/current/analog/8", "high"),
+        ];
+        let lines = || lines.iter().map(|(topic, payload)| (topic.to_string(), payload.to_string()));
+
+        let nodes = ingest_messages(lines());
+        assert_eq!(nodes.len(), 2);
+        let node1 = &nodes["node1"];
+        assert_eq!(node1.collection(5).unwrap().is_off(), true);
+        assert_eq!(node1.collection(5).unwrap().change_count(&(Local::now() - Duration::seconds(10))), 2);
+        assert_eq!(node1.collection(3).unwrap().get_average_temperature(&(Local::now() - Duration::seconds(10))), Some(Temperature::new(20.5_f32)));
+        let node2 = &nodes["node2"];
+        assert_eq!(node2.collection(8).unwrap().active_until().is_some(), true);
+        assert_eq!(node2.collection(2).unwrap().is_on(), true);
+
+        let (_, errors) = ingest_messages_with_errors(lines());
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].0, 3);
+        assert_eq!(errors[1], (6, PinError::InvalidValue("Unable to parse analog value")));
+    }
+
+    #[test]
+    fn test_pin_operation_from_combined_message()
+    {
+        let states = PinOperation::from_combined_message("node1/current/env/5", "t=21.5;a=300", false).unwrap();
+        let values: Vec<(u8, PinValue)> = states.iter().map(|state| (state.pin, state.value.clone())).collect();
+        assert_eq!(values, vec![
+            (5, PinValue::Temperature(Temperature::new(21.5_f32))),
+            (5, PinValue::Analog(300)),
+        ]);
+        assert_eq!(states.iter().all(|state| state.dt == states[0].dt && state.until.is_none()), true);
+
+        // humidity needs a parser in the registry
+        let states = PinOperation::from_combined_message("node1/current/env/5", "t=21.5;h=55;a=300", true).unwrap();
+        assert_eq!(states.len(), 2);
+        assert_eq!(PinOperation::from_combined_message("node1/current/env/5", "t=21.5;h=55;a=300", false).unwrap_err(),
+            PinError::UnknownKind("h".to_string()));
+
+        let states = PinOperation::from_combined_message("node1/timeout/60/env/5", "t=21.5;p=1013;", true).unwrap();
+        assert_eq!(states.len(), 1);
+        assert_eq!(states[0].until.is_some(), true);
+
+        assert_eq!(PinOperation::from_combined_message("node1/current/env/5", "t=21.5;p=1013", false).unwrap_err(),
+            PinError::UnknownKind("p".to_string()));
+        assert_eq!(PinOperation::from_combined_message("node1/current/env/5", "t21.5", true).unwrap_err(),
+            PinError::InvalidValue("Expected key=value in combined payload"));
+        assert_eq!(PinOperation::from_combined_message("node1/env/5", "t=21.5", true).unwrap_err(),
+            PinError::InvalidTopic("Expected current, last or timeout"));
+    }
+
+    #[test]
+    fn test_pin_operation_from_combined_message_with_options()
+    {
+        let mut registry = ParserRegistry::default();
+        registry.register("humidity", |message| {
+            let value = message.parse::<f32>().map_err(|_| PinError::InvalidValue("Unable to parse humidity value"))?;
+            if !(0_f32..=100_f32).contains(&value) {
+                return Err(PinError::OutOfRange(format!("humidity {} outside 0..=100", value)));
+            }
+            Ok(PinValue::Voltage(value))
+        });
+        let states = PinOperation::from_combined_message_with_options("node1/current/env/5", "t=21.5;h=55;a=300", &registry, &ParseOptions::default(), false).unwrap();
+        let values: Vec<PinValue> = states.into_iter().map(|state| state.value).collect();
+        assert_eq!(values, vec![PinValue::Temperature(Temperature::new(21.5_f32)), PinValue::Voltage(55_f32), PinValue::Analog(300)]);
+        assert_eq!(PinOperation::from_combined_message_with_options("node1/current/env/5", "t=21.5;h=120", &registry, &ParseOptions::default(), false).unwrap_err(),
+            PinError::OutOfRange("humidity 120 outside 0..=100".to_string()));
+
+        let now = FixedClock::now();
+        let states: Vec<PinState> = PinOperationIn::from_combined_message_with_clock::<FixedClock>("node1/timeout/60/env/5", "t=21.5;h=55.5;a=300",
+            &registry, &ParseOptions::default(), false).unwrap();
+        assert_eq!(states[1], PinState::new(5, PinValue::Voltage(55.5_f32), now, Some(now + Duration::seconds(60))));
+        assert_eq!(states.iter().all(|state| state.dt == now && state.until == Some(now + Duration::seconds(60))), true);
+
+        let options = ParseOptions { max_payload_len: 8, ..ParseOptions::default() };
+        assert_eq!(PinOperation::from_combined_message_with_options("node1/current/env/5", "t=21.5;h=55;a=300", &registry, &options, false).unwrap_err(),
+            PinError::OutOfRange("payload longer than 8 bytes".to_string()));
+        let options = ParseOptions { max_segments: 3, ..ParseOptions::default() };
+        assert_eq!(PinOperation::from_combined_message_with_options("node1/current/env/5", "t=21.5", &registry, &options, false).unwrap_err(),
+            PinError::OutOfRange("topic has more than 3 segments".to_string()));
+        let options = ParseOptions { max_timeout: Duration::seconds(30), ..ParseOptions::default() };
+        assert_eq!(PinOperation::from_combined_message_with_options("node1/timeout/60/env/5", "t=21.5", &registry, &options, false).unwrap_err(),
+            PinError::OutOfRange("timeout 60s above 30s".to_string()));
+    }
+
+    #[test]
+    fn test_parser_registry()
+    {
+        let mut registry = ParserRegistry::default();
+        registry.register("humidity", |message| {
+            let value = message.parse::<u8>().map_err(|_| PinError::InvalidValue("Unable to parse humidity value"))?;
+            if value > 100 {
+                return Err(PinError::InvalidValue("Humidity above 100%"));
+            }
+            Ok(PinValue::Analog(value as u16))
+        });
+
+        let op = PinOperation::from_parts_with_registry("node1/current/humidity/4", "55", &registry).unwrap();
+        assert_eq!(op.node, "node1");
+        assert_eq!(op.pin_state.pin, 4);
+        assert_eq!(op.pin_state.value, PinValue::Analog(55));
+
+        let op = PinOperation::from_parts_with_registry("node1/current/digital/5", "1", &registry).unwrap();
+        assert_eq!(op.pin_state.value, PinValue::Digital(true));
+
+        assert_eq!(registry.parse("humidity", "120"), Err(PinError::InvalidValue("Humidity above 100%")));
+        assert_eq!(PinOperation::from_parts_with_registry("node1/current/humidity/4", "55", &ParserRegistry::default()).unwrap_err(),
+            PinError::UnknownKind("humidity".to_string()));
+    }
+
+    #[test]
+    fn test_pin_collection_with_labels()
+    {
+        let mut labels = HashMap::new();
+        labels.insert(5, "living_room_heater".to_string());
+        let mut col = PinCollection::default().with_labels(labels);
+        assert_eq!(col.label(5), Some("living_room_heater"));
+        assert_eq!(col.label(6), None);
+        assert_eq!(col.get_last_changed_labeled(), None);
+
+        col.push(&PinState::now(5, PinValue::Digital(true)));
+        let (state, label) = col.get_last_changed_labeled().unwrap();
+        assert_eq!(state.pin, 5);
+        assert_eq!(label, Some("living_room_heater"));
+
+        col.push(&PinState::now(6, PinValue::Digital(false)));
+        assert_eq!(col.get_last_changed_labeled().unwrap().1, None);
+    }
+
+    #[test]
+    fn test_pin_collection_get_last_analog_direction()
+    {
+        let mut col = PinCollection::default();
+        assert_eq!(col.get_last_analog_direction(), None);
+
+        col.push(&PinState::now(4, PinValue::Analog(100)));
+        col.push(&PinState::now(4, PinValue::Digital(true)));
+        assert_eq!(col.get_last_analog_direction(), None);
+
+        col.push(&PinState::now(4, PinValue::Analog(400)));
+        assert_eq!(col.get_last_analog_direction(), Some(Direction::Rising));
+
+        col.push(&PinState::now(4, PinValue::Temperature(Temperature::new(20_f32))));
+        col.push(&PinState::now(4, PinValue::Analog(250)));
+        assert_eq!(col.get_last_analog_direction(), Some(Direction::Falling));
+
+        col.push(&PinState::now(4, PinValue::Analog(250)));
+        assert_eq!(col.get_last_analog_direction(), Some(Direction::Unchanged));
+    }
+
+    #[test]
+    fn test_pin_collection_with_min_sample_interval()
+    {
+        let mut col = PinCollection::default().with_min_sample_interval(Duration::seconds(1));
+        let now = Local::now();
+        let since = now - Duration::seconds(100);
+
+        // 50 readings a second for two seconds
+        for i in 0..100 {
+            col.push(&PinState {pin: 4_u8, value: PinValue::Analog(i), dt: now - Duration::seconds(10) + Duration::milliseconds(i as i64 * 20), until: None});
+        }
+        assert_eq!(col.has_sufficient_samples(&since, 2), true);
+        assert_eq!(col.has_sufficient_samples(&since, 3), false);
+        assert_eq!(col.value_at(&now), Some(PinValue::Analog(50)));
+
+        // other kinds and pins keep their own spacing
+        col.push(&PinState {pin: 4_u8, value: PinValue::Digital(true), dt: now - Duration::seconds(8), until: None});
+        col.push(&PinState {pin: 5_u8, value: PinValue::Analog(1), dt: now - Duration::seconds(8), until: None});
+        assert_eq!(col.has_sufficient_samples(&since, 4), true);
+
+        // a rapid digital toggle is dropped from states but still recorded as a change
+        col.push(&PinState {pin: 4_u8, value: PinValue::Digital(false), dt: now - Duration::seconds(8) + Duration::milliseconds(20), until: None});
+        assert_eq!(col.has_sufficient_samples(&since, 5), false);
+        assert_eq!(col.get_last_changed_digital().unwrap().value, PinValue::Digital(false));
+    }
+
+    #[test]
+    fn test_pin_collection_snapshot()
+    {
+        let mut col = PinCollection::default();
+        let now = Local::now();
+        let since = now - Duration::seconds(100);
+        let snapshot = col.snapshot(&since);
+        let empty = PinSnapshot::default();
+        assert_eq!(snapshot.is_on, empty.is_on);
+        assert_eq!(snapshot.is_off, empty.is_off);
+        assert_eq!(snapshot.last_changed_dt, empty.last_changed_dt);
+        assert_eq!(snapshot.average_temperature, empty.average_temperature);
+        assert_eq!(snapshot.sample_count, empty.sample_count);
+
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(20_f32)), dt: now - Duration::seconds(200), until: None});
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(22_f32)), dt: now - Duration::seconds(50), until: None});
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now - Duration::seconds(20), until: None});
+        let snapshot = col.snapshot(&since);
+        assert_eq!(snapshot.is_on, true);
+        assert_eq!(snapshot.is_off, false);
+        assert_eq!(snapshot.last_changed_dt, Some(now - Duration::seconds(20)));
+        assert_eq!(snapshot.last_changed_value, Some(PinValue::Digital(true)));
+        assert_eq!(snapshot.average_temperature, Some(Temperature::new(22_f32)));
+        assert_eq!(snapshot.sample_count, 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_pin_serde()
+    {
+        let now = Local::now();
+        let state = PinState::new(5, PinValue::Digital(true), now, Some(now + Duration::seconds(60)));
+        let restored: PinState = serde_json::from_str(&serde_json::to_string(&state).unwrap()).unwrap();
+        assert_eq!(restored, state);
+
+        let op = PinOperation::from_parts("building/node1/timeout/60/analog/3", "512").unwrap();
+        let restored: PinOperation = serde_json::from_str(&serde_json::to_string(&op).unwrap()).unwrap();
+        assert_eq!(restored.pin_state, op.pin_state);
+        assert_eq!((restored.node, restored.toggle, restored.raw_topic, restored.raw_payload), (op.node, op.toggle, op.raw_topic, op.raw_payload));
+        let op: UtcPinOperation = serde_json::from_str(r#"{"pin_state":{"pin":3,"value":{"type":"analog","value":512},"dt":"2020-01-01T10:00:00Z","until":null},"node":"node1"}"#).unwrap();
+        assert_eq!(op.pin_state.value, PinValue::Analog(512));
+        assert_eq!(op.toggle, false);
+
+        let mut col = PinCollection::default();
+        col.push(&PinState::new(3, PinValue::Temperature(Temperature::new(22_f32)), now - Duration::seconds(50), None));
+        col.push(&PinState::new(1, PinValue::Digital(true), now - Duration::seconds(20), None));
+        let json = serde_json::to_value(col.snapshot(&(now - Duration::seconds(100)))).unwrap();
+        assert_eq!(json["is_on"], true);
+        assert_eq!(json["last_changed_value"], serde_json::json!({"type": "digital", "value": true}));
+        assert_eq!(json["average_temperature"], 22.0);
+        assert_eq!(json["sample_count"], 2);
+        assert_eq!(json["last_changed_dt"].as_str().is_some(), true);
+    }
+
+    #[test]
+    fn test_pin_collection_with_ignored_change_pins()
+    {
+        let mut col = PinCollection::default().with_ignored_change_pins(&[7]);
+        let since = Local::now() - Duration::seconds(100);
+
+        col.push(&PinState::now(7, PinValue::Digital(true)));
+        col.push(&PinState::now(7, PinValue::Digital(false)));
+        col.push(&PinState::now(7, PinValue::Analog(100)));
+        assert_eq!(col.get_last_changed(), None);
+        assert_eq!(col.has_sufficient_samples(&since, 3), true);
+
+        col.push(&PinState::now(2, PinValue::Digital(true)));
+        col.push(&PinState::now(7, PinValue::Digital(true)));
+        col.push(&PinState::now(2, PinValue::Digital(false)));
+        let changes = col.changes_since(&since);
+        assert_eq!(changes.len(), 2);
+        assert!(changes.iter().all(|state| state.pin == 2));
+        assert_eq!(col.has_sufficient_samples(&since, 6), true);
+    }
+
+    #[test]
+    fn test_pin_collection_get_last_changed_by_kind()
+    {
+        let mut col = PinCollection::default();
+        assert_eq!(col.get_last_changed_digital(), None);
+        assert_eq!(col.get_last_changed_analog(), None);
+
+        col.push(&PinState::now(4, PinValue::Digital(true)));
+        col.push(&PinState::now(5, PinValue::Analog(300)));
+        col.push(&PinState::now(4, PinValue::Digital(false)));
+        col.push(&PinState::now(5, PinValue::Analog(0)));
+        col.push(&PinState::now(3, PinValue::Temperature(Temperature::new(20_f32))));
+
+        assert_eq!(col.get_last_changed_digital().unwrap().value, PinValue::Digital(false));
+        assert_eq!(col.get_last_changed_analog().unwrap().value, PinValue::Analog(0));
+
+        col.push(&PinState::now(4, PinValue::Digital(true)));
+        assert_eq!(col.get_last_changed_digital().unwrap().value, PinValue::Digital(true));
+        assert_eq!(col.get_last_changed_analog().unwrap().value, PinValue::Analog(0));
+        assert_eq!(col.get_last_changed().unwrap().value, PinValue::Digital(true));
+    }
+
+    #[test]
+    fn test_pin_collection_from_states_unordered()
+    {
+        let now = Local::now();
+        let since = now - Duration::seconds(100);
+        let state = |offset, value| PinState {pin: 1_u8, value: PinValue::Digital(value), dt: now - Duration::seconds(offset), until: None};
+        let mut states = vec![state(10, false), state(30, true), state(20, true), state(40, false)];
+
+        let col = PinCollection::from_states(&states);
+        let changes: Vec<PinState> = col.changes_since(&since);
+        assert_eq!(changes, vec![state(10, false), state(30, true), state(40, false)]);
+        assert_eq!(col.is_off(), true);
+
+        sort_states_by_time(&mut states);
+        assert_eq!(states, vec![state(40, false), state(30, true), state(20, true), state(10, false)]);
+    }
+
+    #[test]
+    fn test_pin_collection_merge()
+    {
+        let now = Local::now();
+        let since = now - Duration::seconds(100);
+        let mut col_a = PinCollection::default();
+        let mut col_b = PinCollection::default();
+        col_a.push(&PinState::new(4, PinValue::Digital(true), now - Duration::seconds(50), None));
+        col_a.push(&PinState::new(4, PinValue::Digital(true), now - Duration::seconds(30), None));
+        col_a.push(&PinState::new(4, PinValue::Digital(false), now - Duration::seconds(10), None));
+        col_b.push(&PinState::new(4, PinValue::Digital(false), now - Duration::seconds(40), None));
+        col_b.push(&PinState::new(4, PinValue::Digital(false), now - Duration::seconds(20), None));
+
+        col_a.merge(&col_b);
+        assert_eq!(col_a.has_sufficient_samples(&since, 5), true);
+        let changes: Vec<(PinValue, DateTime<Local>)> = col_a.changes_since(&since).into_iter().map(|state| (state.value, state.dt)).collect();
+        assert_eq!(changes, vec![
+            (PinValue::Digital(false), now - Duration::seconds(20)),
+            (PinValue::Digital(true), now - Duration::seconds(30)),
+            (PinValue::Digital(false), now - Duration::seconds(40)),
+            (PinValue::Digital(true), now - Duration::seconds(50)),
+        ]);
+        assert_eq!(col_a.last_update_dt(), Some(now - Duration::seconds(10)));
+
+        // temperatures are kept apart and do not evict the digital states
+        let mut col_c = PinCollection::default();
+        for i in 0..20 {
+            col_c.push(&PinState::new(3, PinValue::Temperature(Temperature::new(30_f32)), now - Duration::seconds(45) + Duration::seconds(i), None));
+        }
+        col_a.merge(&col_c);
+        assert_eq!(col_a.has_sufficient_samples(&since, 25), true);
+        assert_eq!(col_a.has_sufficient_samples(&since, 26), false);
+        assert_eq!(col_a.has_sufficient_samples(&(now - Duration::seconds(42)), 20), true);
+        assert_eq!(col_a.get_last_changed_value(), Some(PinValue::Digital(false)));
+    }
+
+    #[test]
+    fn test_pin_collection_merge_keeps_changes()
+    {
+        let now = Local::now();
+        let mut col_a = PinCollection::default();
+        let mut col_b = PinCollection::default();
+        col_a.push(&PinState::new(3, PinValue::Temperature(Temperature::new(20_f32)), now - Duration::seconds(60), None));
+
+        // the state that turned the pin on ages out of col_b's samples
+        col_b.push(&PinState::new(4, PinValue::Digital(true), now - Duration::seconds(120), Some(now + Duration::seconds(600))));
+        for i in 0..PinCollection::CAPACITY as i64 {
+            col_b.push(&PinState::new(4, PinValue::Digital(true), now - Duration::seconds(100) + Duration::seconds(i), None));
+        }
+        col_b.push_forced(&PinState::new(5, PinValue::Analog(700), now - Duration::seconds(50), None));
+        assert_eq!(col_b.oldest_dt(), Some(now - Duration::seconds(100)));
+
+        col_a.merge(&col_b);
+        let last = col_a.get_last_changed().unwrap();
+        assert_eq!((last.value, last.dt), (PinValue::Analog(700), now - Duration::seconds(50)));
+        let changes: Vec<(u8, DateTime<Local>)> = col_a.changes_since(&(now - Duration::seconds(300))).into_iter().map(|state| (state.pin, state.dt)).collect();
+        assert_eq!(changes, vec![(5, now - Duration::seconds(50)), (4, now - Duration::seconds(120))]);
+        assert_eq!(col_a.changes_since(&(now - Duration::seconds(300)))[1].until, Some(now + Duration::seconds(600)));
+        assert_eq!(col_a.is_on(), true);
+
+        // merging again does not duplicate the recorded changes
+        col_a.merge(&col_b);
+        assert_eq!(col_a.change_count(&(now - Duration::seconds(300))), 2);
+
+        // merging with an empty collection keeps forced changes and the version
+        let now = Local::now();
+        let mut col_d = PinCollection::default();
+        col_d.push(&PinState::new(4, PinValue::Digital(true), now - Duration::seconds(20), None));
+        col_d.push_forced(&PinState::new(4, PinValue::Digital(true), now - Duration::seconds(10), None));
+        let version = col_d.version();
+        col_d.merge(&PinCollection::default());
+        assert_eq!(col_d.change_count(&(now - Duration::seconds(300))), 2);
+        assert_eq!(col_d.len(), 2);
+        assert_eq!(col_d.version(), version);
+
+        // col_d's forced change is kept, its first on change only repeats the one of col_e and is dropped,
+        // samples are not thinned by min_sample_interval
+        let mut col_e = PinCollection::default().with_min_sample_interval(Duration::seconds(60));
+        col_e.push(&PinState::new(4, PinValue::Digital(true), now - Duration::seconds(30), None));
+        col_e.merge(&col_d);
+        let changes: Vec<DateTime<Local>> = col_e.changes_since(&(now - Duration::seconds(300))).into_iter().map(|state| state.dt).collect();
+        assert_eq!(changes, vec![now - Duration::seconds(10), now - Duration::seconds(30)]);
+        assert_eq!(col_e.len(), 3);
+        assert_eq!(col_e.version(), 2);
+
+        // the seeded baseline survives the merge
+        let mut col_c = PinCollection::default();
+        col_c.seed(&PinState::new(8, PinValue::Analog(300), now - Duration::seconds(200), None));
+        col_c.merge(&PinCollection::default());
+        col_c.push(&PinState::now(8, PinValue::Analog(310)));
+        assert_eq!(col_c.change_count(&(now - Duration::seconds(300))), 0);
+    }
+
+    #[test]
+    fn test_pin_collection_clear()
+    {
+        let mut col = PinCollection::default();
+        let now = Local::now();
+        let since = now - Duration::seconds(100);
+        col.push(&PinState::new(4, PinValue::Digital(true), now - Duration::seconds(30), None));
+        col.push(&PinState::new(3, PinValue::Temperature(Temperature::new(20_f32)), now - Duration::seconds(20), None));
+        col.push(&PinState::new(3, PinValue::Temperature(Temperature::new(22_f32)), now - Duration::seconds(10), None));
+
+        col.clear_states_before(&(now - Duration::seconds(15)));
+        assert_eq!(col.get_average_temperature(&since), Some(Temperature::new(22_f32)));
+        assert_eq!(col.has_sufficient_samples(&since, 2), false);
+        assert_eq!(col.get_last_changed_value(), Some(PinValue::Digital(true)));
+
+        col.push(&PinState::now(4, PinValue::Digital(true)));
+        assert_eq!(col.changes_since(&since).len(), 1);
+
+        col.clear();
+        assert_eq!(col.get_average_temperature(&since), None);
+        assert_eq!(col.get_last_changed(), None);
+        assert_eq!(col.last_update_dt(), None);
+
+        col.push(&PinState::now(4, PinValue::Digital(true)));
+        assert_eq!(col.get_last_changed_value(), Some(PinValue::Digital(true)));
+    }
+
+    #[test]
+    fn test_pin_collection_value_at()
+    {
+        let mut col = PinCollection::default();
+        let now = Local::now();
+        assert_eq!(col.value_at(&now), None);
+
+        col.push(&PinState::new(4, PinValue::Digital(true), now - Duration::seconds(30), None));
+        col.push(&PinState::new(5, PinValue::Analog(200), now - Duration::seconds(20), None));
+        col.push(&PinState::new(4, PinValue::Digital(false), now - Duration::seconds(10), None));
+
+        assert_eq!(col.value_at(&(now - Duration::seconds(40))), None);
+        assert_eq!(col.value_at(&(now - Duration::seconds(30))), Some(PinValue::Digital(true)));
+        assert_eq!(col.value_at(&(now - Duration::seconds(25))), Some(PinValue::Digital(true)));
+        assert_eq!(col.value_at(&(now - Duration::seconds(15))), Some(PinValue::Analog(200)));
+        assert_eq!(col.value_at(&now), Some(PinValue::Digital(false)));
+    }
+
+    #[test]
+    fn test_pin_collection_per_kind_retention()
+    {
+        let mut col = PinCollection::default();
+        let now = Local::now();
+        let since = now - Duration::seconds(100);
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(20_f32)), dt: now - Duration::seconds(60), until: None});
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(22_f32)), dt: now - Duration::seconds(50), until: None});
+        for i in 0..25 {
+            col.push(&PinState {pin: 1_u8, value: PinValue::Digital(i % 2 == 0), dt: now - Duration::seconds(40) + Duration::seconds(i), until: None});
+        }
+        assert_eq!(col.get_average_temperature(&since), Some(Temperature::new(21_f32)));
+        assert_eq!(col.last_n_temperatures(5), vec![22_f32, 20_f32]);
+
+        let states = col.last_n_states(100);
+        assert_eq!(states.len(), 22);
+        assert_eq!(states[0].value, PinValue::Digital(true));
+        assert_eq!(states[20].value, PinValue::Temperature(Temperature::new(22_f32)));
+        assert_eq!(col.oldest_state().unwrap().value, PinValue::Temperature(Temperature::new(20_f32)));
+        assert_eq!(col.last_update_dt(), Some(now - Duration::seconds(16)));
+
+        let col: PinCollection = PinCollectionIn::new(states.iter().take(20).cloned().collect(), ArrayDeque::new());
+        assert_eq!(col.last_n_states(100), states[..20].to_vec());
+    }
+
+    #[test]
+    fn test_pin_collection_capacity()
+    {
+        let mut col = PinCollection::default();
+        assert_eq!(col.capacity(), PinCollection::CAPACITY);
+        assert_eq!(col.is_empty(), true);
+
+        for i in 0..=PinCollection::CAPACITY {
+            col.push(&PinState::now(4, PinValue::Analog(i as u16)));
+        }
+        assert_eq!(col.len(), PinCollection::CAPACITY);
+        assert_eq!(col.oldest_state().unwrap().value, PinValue::Analog(1));
+
+        col.push(&PinState::now(3, PinValue::Temperature(Temperature::new(20_f32))));
+        assert_eq!(col.len(), PinCollection::CAPACITY + 1);
+
+        // each kind is bounded on its own, the total is bounded by four buffers
+        for i in 0..=2 * PinCollection::CAPACITY {
+            col.push(&PinState::now(3, PinValue::Temperature(Temperature::new(i as f32))));
+            col.push(&PinState::now(5, PinValue::Digital(i % 2 == 0)));
+            col.push(&PinState::now(6, PinValue::Voltage(i as f32)));
+        }
+        assert_eq!(col.len(), 4 * PinCollection::CAPACITY);
+        assert_eq!(col.last_n_states(100).len(), 4 * PinCollection::CAPACITY);
+        assert_eq!(col.last_n_states(1)[0].value, PinValue::Voltage(40_f32));
+        assert_eq!(col.oldest_state().unwrap().value, PinValue::Analog(1));
+    }
+
+    #[test]
+    fn test_pin_collection_oldest_state()
+    {
+        let mut col = PinCollection::default();
+        let now = Local::now();
+        assert_eq!(col.oldest_state(), None);
+        assert_eq!(col.oldest_dt(), None);
+
+        for i in 0..5 {
+            col.push(&PinState {pin: 4_u8, value: PinValue::Analog(i), dt: now - Duration::seconds(50 - i as i64), until: None});
+        }
+        assert_eq!(col.oldest_state().unwrap().value, PinValue::Analog(0));
+        assert_eq!(col.oldest_dt(), Some(now - Duration::seconds(50)));
+        assert_eq!(col.last_update_dt(), Some(now - Duration::seconds(46)));
+
+        for i in 5..25 {
+            col.push(&PinState {pin: 4_u8, value: PinValue::Analog(i), dt: now - Duration::seconds(50 - i as i64), until: None});
+        }
+        assert_eq!(col.oldest_state().unwrap().value, PinValue::Analog(5));
+    }
+
+    #[test]
+    fn test_pin_collection_interpolated_temperature_at()
+    {
+        let mut col = PinCollection::default();
+        let now = Local::now();
+        assert_eq!(col.interpolated_temperature_at(&now), None);
+
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(20_f32)), dt: now - Duration::seconds(60), until: None});
+        col.push(&PinState {pin: 3_u8, value: PinValue::Analog(900), dt: now - Duration::seconds(40), until: None});
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(26_f32)), dt: now, until: None});
+
+        assert_eq!(col.interpolated_temperature_at(&(now - Duration::seconds(30))), Some(Temperature::new(23_f32)));
+        assert_eq!(col.interpolated_temperature_at(&(now - Duration::seconds(45))), Some(Temperature::new(21.5_f32)));
+        assert_eq!(col.interpolated_temperature_at(&(now - Duration::seconds(60))), Some(Temperature::new(20_f32)));
+        assert_eq!(col.interpolated_temperature_at(&now), Some(Temperature::new(26_f32)));
+        assert_eq!(col.interpolated_temperature_at(&(now - Duration::seconds(61))), None);
+        assert_eq!(col.interpolated_temperature_at(&(now + Duration::seconds(1))), None);
+    }
+
+    #[test]
+    fn test_pin_collection_temperature_threshold()
+    {
+        let temperature = |value: f32| PinState::now(3, PinValue::Temperature(Temperature::new(value)));
+        let mut col = PinCollection::default();
+        assert_eq!(col.push(&temperature(25_f32)), None);
+
+        col.set_temperature_threshold(21_f32, CrossDirection::Either);
+        assert_eq!(col.push(&temperature(19_f32)), Some(ThresholdEvent::new(3, 21_f32, CrossDirection::Falling, Temperature::new(19_f32))));
+        assert_eq!(col.push(&temperature(20_f32)), None);
+        assert_eq!(col.push(&PinState::now(4, PinValue::Digital(true))), None);
+        assert_eq!(col.push(&temperature(21_f32)), Some(ThresholdEvent::new(3, 21_f32, CrossDirection::Rising, Temperature::new(21_f32))));
+        assert_eq!(col.push(&temperature(23_f32)), None);
+        assert_eq!(col.push(&temperature(22_f32)), None);
+
+        col.set_temperature_threshold(21_f32, CrossDirection::Rising);
+        assert_eq!(col.push(&temperature(18_f32)), None);
+        assert_eq!(col.push(&temperature(24_f32)).map(|event| event.direction), Some(CrossDirection::Rising));
+
+        col.set_temperature_threshold(21_f32, CrossDirection::Falling);
+        assert_eq!(col.push(&temperature(20.5_f32)).map(|event| event.direction), Some(CrossDirection::Falling));
+        assert_eq!(col.push(&temperature(30_f32)), None);
+    }
+
+    #[test]
+    fn test_pin_collection_changes_since()
+    {
+        let mut col = PinCollection::default();
+        let now = Local::now();
+        assert_eq!(col.changes_since(&(now - Duration::seconds(100))), vec![]);
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now - Duration::seconds(50), until: None});
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(false), dt: now - Duration::seconds(40), until: None});
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(false), dt: now - Duration::seconds(30), until: None});
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now - Duration::seconds(20), until: None});
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(false), dt: now - Duration::seconds(10), until: None});
+
+        let changes = col.changes_since(&(now - Duration::seconds(40)));
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].dt, now - Duration::seconds(10));
+        assert_eq!(changes[1].dt, now - Duration::seconds(20));
+
+        assert_eq!(col.changes_since(&(now - Duration::seconds(100))).len(), 4);
+        assert_eq!(col.changes_since(&now), vec![]);
+    }
+
+    #[test]
+    fn test_pin_collection_is_flatlined()
+    {
+        let now = Local::now();
+        let since = now - Duration::seconds(100);
+        let mut col = PinCollection::default();
+        assert_eq!(col.is_flatlined(&since, 0), false);
+
+        for value in &[21.5_f32, 21.5_f32, 21.5004_f32] {
+            col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(*value)), dt: now, until: None});
+        }
+        assert_eq!(col.is_flatlined(&since, 3), true);
+        assert_eq!(col.is_flatlined(&since, 4), false);
+
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(21.7_f32)), dt: now, until: None});
+        assert_eq!(col.is_flatlined(&since, 3), false);
+
+        let mut col = PinCollection::default();
+        for value in &[300_u16, 300, 300, 300] {
+            col.push(&PinState {pin: 4_u8, value: PinValue::Analog(*value), dt: now, until: None});
+        }
+        col.push(&PinState {pin: 4_u8, value: PinValue::Analog(512), dt: now - Duration::seconds(200), until: None});
+        assert_eq!(col.is_flatlined(&since, 4), true);
+        col.push(&PinState {pin: 4_u8, value: PinValue::Digital(true), dt: now, until: None});
+        assert_eq!(col.is_flatlined(&since, 4), false);
+    }
+
+    #[test]
+    fn test_pin_collection_is_stale()
+    {
+        let mut col = PinCollection::default();
+        assert_eq!(col.last_update_dt(), None);
+        assert_eq!(col.is_stale(Duration::seconds(60)), true);
+
+        let dt = Local::now() - Duration::seconds(120);
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(20_f32)), dt, until: None});
+        assert_eq!(col.last_update_dt(), Some(dt));
+        assert_eq!(col.is_stale(Duration::seconds(60)), true);
+        assert_eq!(col.is_stale(Duration::seconds(300)), false);
+
+        let dt = Local::now();
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(21_f32)), dt, until: None});
+        assert_eq!(col.last_update_dt(), Some(dt));
+        assert_eq!(col.is_stale(Duration::seconds(60)), false);
+    }
+
+    #[test]
+    fn test_pin_collection_change_count()
+    {
+        let mut col = PinCollection::default();
+        let now = Local::now();
+        assert_eq!(col.change_count(&(now - Duration::seconds(120))), 0);
+        assert_eq!(col.toggle_rate_per_minute(&(now - Duration::seconds(120))), 0_f32);
+
+        for (i, offset) in [110, 100, 90, 80, 50, 40, 30, 20].iter().enumerate() {
+            col.push(&PinState::new(5, PinValue::Digital(i % 2 == 0), now - Duration::seconds(*offset), None));
+            col.push(&PinState::new(5, PinValue::Digital(i % 2 == 0), now - Duration::seconds(*offset - 5), None));
+        }
+        assert_eq!(col.change_count(&(now - Duration::seconds(120))), 8);
+        assert_eq!(col.change_count(&(now - Duration::seconds(60))), 4);
+        assert_eq!(col.change_count(&(now - Duration::seconds(20))), 0);
+
+        let rate = col.toggle_rate_per_minute(&(now - Duration::seconds(120)));
+        assert!(rate > 3.9_f32 && rate <= 4_f32);
+        assert_eq!(col.toggle_rate_per_minute(&(now + Duration::seconds(60))), 0_f32);
+    }
+
+    #[test]
+    fn test_pin_collection_next_expected_sample()
+    {
+        let mut col = PinCollection::default();
+        let now = Local::now();
+        let since = now - Duration::seconds(100);
+        assert_eq!(col.next_expected_sample(&since), None);
+
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(20_f32)), dt: now - Duration::seconds(30), until: None});
+        assert_eq!(col.next_expected_sample(&since), None);
+
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(21_f32)), dt: now - Duration::seconds(20), until: None});
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(22_f32)), dt: now - Duration::seconds(10), until: None});
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(23_f32)), dt: now, until: None});
+        assert_eq!(col.next_expected_sample(&since), Some(now + Duration::seconds(10)));
+
+        assert_eq!(col.next_expected_sample(&(now - Duration::seconds(15))), Some(now + Duration::seconds(10)));
+        assert_eq!(col.next_expected_sample(&(now - Duration::seconds(5))), None);
+    }
+
+    #[test]
+    fn test_pin_collection_temperature_stats()
+    {
+        let now = Local::now();
+        let mut col = PinCollection::default();
+        assert_eq!(col.get_temperature_stats(&(now - Duration::seconds(100))), None);
+
+        for (i, value) in [30_f32, 18_f32, 22.5_f32, 16_f32, 21.5_f32].iter().enumerate() {
+            col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(*value)), dt: now - Duration::seconds(50 - i as i64 * 10), until: None});
+        }
+        col.push(&PinState::now(3, PinValue::Analog(1000)));
+
+        let stats = col.get_temperature_stats(&(now - Duration::seconds(45))).unwrap();
+        assert_eq!(stats, TemperatureStats::new(Temperature::new(19.5_f32), Temperature::new(16_f32), Temperature::new(22.5_f32), 4));
+        assert_eq!(Some(stats.average), col.get_average_temperature(&(now - Duration::seconds(45))));
+        assert_eq!(col.get_temperature_stats(&now), None);
+    }
+
+    #[test]
+    fn test_pin_collection_partial_temperature_average()
+    {
+        let since = Local::now() - Duration::seconds(100);
+        let mut col_a = PinCollection::default();
+        let mut col_b = PinCollection::default();
+        assert_eq!(col_a.partial_temperature_average(&since), None);
+
+        for value in &[10_f32, 20_f32] {
+            col_a.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(*value)), dt: Local::now(), until: None});
+        }
+        for value in &[16_f32, 18_f32, 20_f32, 22_f32] {
+            col_b.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(*value)), dt: Local::now(), until: None});
+        }
+        col_b.push(&PinState {pin: 3_u8, value: PinValue::Analog(1000), dt: Local::now(), until: None});
+
+        let (avg_a, count_a) = col_a.partial_temperature_average(&since).unwrap();
+        let (avg_b, count_b) = col_b.partial_temperature_average(&since).unwrap();
+        assert_eq!((avg_a, count_a), (15_f32, 2));
+        assert_eq!((avg_b, count_b), (19_f32, 4));
+        assert_eq!(combine_averages(avg_a, count_a, avg_b, count_b), average(&[10_f32, 20_f32, 16_f32, 18_f32, 20_f32, 22_f32]));
+    }
+
+    #[test]
+    fn test_pin_collection_degree_seconds()
+    {
+        let now = FixedClock::now();
+        let since = now - Duration::seconds(1000);
+        let mut col: PinCollectionIn<Local, FixedClock> = PinCollectionIn::default();
+        assert_eq!(col.degree_seconds_above(20_f32, &since), 0_f32);
+
+        // 22 held for 300s, 18 for 200s, 25 for 100s until now
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(22_f32)), dt: now - Duration::seconds(600), until: None});
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(18_f32)), dt: now - Duration::seconds(300), until: None});
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now - Duration::seconds(150), until: None});
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(25_f32)), dt: now - Duration::seconds(100), until: None});
+
+        assert_eq!(col.degree_seconds_above(20_f32, &since), 2_f32 * 300_f32 + 5_f32 * 100_f32);
+        assert_eq!(col.degree_seconds_below(20_f32, &since), 2_f32 * 200_f32);
+        assert_eq!(col.degree_seconds_above(20_f32, &(now - Duration::seconds(200))), 500_f32);
+        assert_eq!(col.degree_seconds_below(30_f32, &now), 0_f32);
+    }
+
+    #[test]
+    fn test_pin_collection_get_time_weighted_average_temperature()
+    {
+        let mut col = PinCollection::default();
+        let now = Local::now();
+        let since = now - Duration::seconds(1000);
+        assert_eq!(col.get_time_weighted_average_temperature(&since), None);
+
+        // 10 held for 540s, 30 held for 60s
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(10_f32)), dt: now - Duration::seconds(600), until: None});
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now - Duration::seconds(300), until: None});
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(30_f32)), dt: now - Duration::seconds(60), until: None});
+
+        let weighted = col.get_time_weighted_average_temperature(&since).unwrap();
+        assert!((weighted.value - 12_f32).abs() < 0.01);
+        assert_eq!(col.get_average_temperature(&since).unwrap(), Temperature::new(20_f32));
+
+        // only the latest reading remains in the window
+        let weighted = col.get_time_weighted_average_temperature(&(now - Duration::seconds(100))).unwrap();
+        assert_eq!(weighted, Temperature::new(30_f32));
+
+        assert_eq!(col.get_time_weighted_average_temperature(&now), None);
+    }
+
+    #[test]
+    fn test_pin_collection_temperature_drift()
+    {
+        let mut col = PinCollection::default();
+        let now = Local::now();
+        let since = now - Duration::seconds(100);
+        let split = now - Duration::seconds(50);
+        assert_eq!(col.temperature_drift(&since, &split), None);
+
+        for (offset, value) in &[(90, 20_f32), (80, 20.5_f32), (70, 21_f32), (60, 21.5_f32)] {
+            col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(*value)), dt: now - Duration::seconds(*offset), until: None});
+        }
+        assert_eq!(col.temperature_drift(&since, &split), None);
+
+        for (offset, value) in &[(40, 22_f32), (30, 22.5_f32), (20, 23_f32), (10, 23.5_f32)] {
+            col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(*value)), dt: now - Duration::seconds(*offset), until: None});
+        }
+        assert_eq!(col.temperature_drift(&since, &split), Some(2_f32));
+        assert_eq!(col.temperature_drift(&(now - Duration::seconds(75)), &split), Some(22.75_f32 - 21.25_f32));
+        assert_eq!(col.temperature_drift(&(now - Duration::seconds(45)), &split), None);
+    }
+
+    #[test]
+    fn test_pin_collection_temperature_skew_indicator()
+    {
+        let since = Local::now() - Duration::seconds(100);
+        let mut col = PinCollection::default();
+        assert_eq!(col.temperature_skew_indicator(&since), None);
+
+        for value in &[18_f32, 19_f32, 20_f32, 21_f32, 22_f32] {
+            col.push(&PinState::now(3, PinValue::Temperature(Temperature::new(*value))));
+        }
+        assert_eq!(col.temperature_skew_indicator(&since), Some(0_f32));
+
+        let mut col = PinCollection::default();
+        for value in &[20_f32, 20_f32, 20_f32, 21_f32, 34_f32] {
+            col.push(&PinState::now(3, PinValue::Temperature(Temperature::new(*value))));
+        }
+        assert_eq!(col.temperature_skew_indicator(&since), Some(3_f32));
+    }
+
+    #[test]
+    fn test_pin_collection_temperature_percentile()
+    {
+        let mut col = PinCollection::default();
+        let since = Local::now() - Duration::seconds(100);
+        assert_eq!(col.temperature_percentile(&since, 95_f32), None);
+
+        for value in &[21_f32, 19_f32, 25_f32, 20_f32, 30_f32] {
+            col.push(&PinState::now(3, PinValue::Temperature(Temperature::new(*value))));
+        }
+        col.push(&PinState::now(1, PinValue::Digital(true)));
+        assert_eq!(col.temperature_percentile(&since, 50_f32), Some(Temperature::new(21_f32)));
+        assert_eq!(col.temperature_percentile(&since, 75_f32), Some(Temperature::new(25_f32)));
+        assert_eq!(col.temperature_percentile(&since, 95_f32), Some(Temperature::new(29_f32)));
+        assert_eq!(col.temperature_percentile(&since, 120_f32), Some(Temperature::new(30_f32)));
+        assert_eq!(col.temperature_percentile(&(since + Duration::seconds(200)), 50_f32), None);
+    }
+
+    #[test]
+    fn test_pin_collection_downsample_temperatures()
+    {
+        let now = FixedClock::now();
+        let since = now - Duration::seconds(100);
+        let mut col: PinCollectionIn<Local, FixedClock> = PinCollectionIn::default();
+        assert_eq!(col.downsample_temperatures(&since, 4).len(), 0);
+
+        for (offset, value) in &[(90, 10_f32), (80, 20_f32), (60, 99_f32), (30, 30_f32), (10, 40_f32), (0, 50_f32)] {
+            let value = if *offset == 60 { PinValue::Analog(99) } else { PinValue::Temperature(Temperature::new(*value)) };
+            col.push(&PinState {pin: 3_u8, value, dt: now - Duration::seconds(*offset), until: None});
+        }
+        let points = col.downsample_temperatures(&since, 2);
+        assert_eq!(points, vec![
+            (now - Duration::seconds(75), Temperature::new(15_f32)),
+            (now - Duration::seconds(25), Temperature::new(40_f32)),
+        ]);
+
+        let points = col.downsample_temperatures(&since, 4);
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0], (now - Duration::seconds(87) - Duration::milliseconds(500), Temperature::new(15_f32)));
+        assert_eq!(points[1], (now - Duration::seconds(37) - Duration::milliseconds(500), Temperature::new(30_f32)));
+        assert_eq!(points[2], (now - Duration::seconds(12) - Duration::milliseconds(500), Temperature::new(45_f32)));
+
+        assert_eq!(col.downsample_temperatures(&since, 0).len(), 0);
+        assert_eq!(col.downsample_temperatures(&now, 2).len(), 0);
+    }
+
+    #[test]
+    fn test_pin_collection_max_reading_gap()
+    {
+        let mut col = PinCollection::default();
+        let now = Local::now();
+        let since = now - Duration::seconds(100);
+        assert_eq!(col.max_reading_gap(&since), None);
+
+        col.push(&PinState {pin: 3_u8, value: PinValue::Analog(1), dt: now - Duration::seconds(90), until: None});
+        assert_eq!(col.max_reading_gap(&since), None);
+
+        col.push(&PinState {pin: 3_u8, value: PinValue::Analog(2), dt: now - Duration::seconds(80), until: None});
+        col.push(&PinState {pin: 3_u8, value: PinValue::Analog(3), dt: now - Duration::seconds(35), until: None});
+        col.push(&PinState {pin: 3_u8, value: PinValue::Analog(4), dt: now - Duration::seconds(30), until: None});
+        col.push(&PinState {pin: 3_u8, value: PinValue::Analog(5), dt: now - Duration::seconds(60), until: None});
+        assert_eq!(col.max_reading_gap(&since), Some(Duration::seconds(25)));
+        assert_eq!(col.max_reading_gap(&(now - Duration::seconds(50))), Some(Duration::seconds(5)));
+    }
+
+    #[test]
+    fn test_pin_collection_has_sufficient_samples()
+    {
+        let mut col = PinCollection::default();
+        let now = Local::now();
+        let since = now - Duration::seconds(100);
+        assert_eq!(col.has_sufficient_samples(&since, 0), true);
+        assert_eq!(col.has_sufficient_samples(&since, 1), false);
+
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(20_f32)), dt: now - Duration::seconds(200), until: None});
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(21_f32)), dt: now - Duration::seconds(20), until: None});
+        col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(22_f32)), dt: now - Duration::seconds(10), until: None});
+        assert_eq!(col.has_sufficient_samples(&since, 1), true);
+        assert_eq!(col.has_sufficient_samples(&since, 2), true);
+        assert_eq!(col.has_sufficient_samples(&since, 3), false);
+        assert_eq!(col.has_sufficient_samples(&(now - Duration::seconds(300)), 3), true);
+    }
+
+    #[test]
+    fn test_pin_collection_coalesced_changes()
+    {
+        let mut col = PinCollection::default();
+        let now = Local::now();
+        assert_eq!(col.coalesced_changes(), Vec::new());
+
+        for (value, seconds) in &[(true, 50), (true, 40), (false, 30), (true, 20), (true, 15), (true, 10)] {
+            col.push_forced(&PinState {pin: 5_u8, value: PinValue::Digital(*value), dt: now - Duration::seconds(*seconds), until: None});
+        }
+        let changes: Vec<(PinValue, DateTime<Local>)> = col.coalesced_changes().into_iter().map(|state| (state.value, state.dt)).collect();
+        assert_eq!(changes, vec![
+            (PinValue::Digital(true), now - Duration::seconds(50)),
+            (PinValue::Digital(false), now - Duration::seconds(30)),
+            (PinValue::Digital(true), now - Duration::seconds(20)),
+        ]);
+    }
+
+    #[test]
+    fn test_pin_collection_mode()
+    {
+        let mut col = PinCollection::default();
+        let now = Local::now();
+        let since = now - Duration::seconds(100);
+        assert_eq!(col.mode_digital(&since), None);
+        assert_eq!(col.mode_analog(&since), None);
+
+        for value in &[true, false, true, true] {
+            col.push(&PinState {pin: 1_u8, value: PinValue::Digital(*value), dt: now, until: None});
+        }
+        for value in &[512_u16, 100, 512, 7] {
+            col.push(&PinState {pin: 1_u8, value: PinValue::Analog(*value), dt: now, until: None});
+        }
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(false), dt: now - Duration::seconds(200), until: None});
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(false), dt: now - Duration::seconds(200), until: None});
+        assert_eq!(col.mode_digital(&since), Some(true));
+        assert_eq!(col.mode_analog(&since), Some(512));
+
+        for _ in 0..2 {
+            col.push(&PinState {pin: 1_u8, value: PinValue::Digital(false), dt: now, until: None});
+        }
+        col.push(&PinState {pin: 1_u8, value: PinValue::Analog(100), dt: now, until: None});
+        assert_eq!(col.mode_digital(&since), None);
+        assert_eq!(col.mode_analog(&since), None);
+    }
+
+    #[test]
+    fn test_pin_collection_distinct_analog_count()
+    {
+        let mut col = PinCollection::default();
+        let now = Local::now();
+        let since = now - Duration::seconds(100);
+        assert_eq!(col.distinct_analog_count(&since), 0);
+
+        for value in &[512_u16, 512, 512, 512] {
+            col.push(&PinState {pin: 1_u8, value: PinValue::Analog(*value), dt: now, until: None});
+        }
+        assert_eq!(col.distinct_analog_count(&since), 1);
+
+        for value in &[0_u16, 100, 512, 1023] {
+            col.push(&PinState {pin: 1_u8, value: PinValue::Analog(*value), dt: now, until: None});
+        }
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now, until: None});
+        col.push(&PinState {pin: 1_u8, value: PinValue::Analog(7), dt: now - Duration::seconds(200), until: None});
+        assert_eq!(col.distinct_analog_count(&since), 4);
+    }
+
+    #[test]
+    fn test_pin_collection_smoothed_temperature_rate()
+    {
+        let mut col = PinCollection::default();
+        let now = Local::now();
+        let since = now - Duration::seconds(100);
+        assert_eq!(col.smoothed_temperature_rate(&since, 2), None);
+
+        // 0.1 degrees per second with alternating noise
+        for i in 0..10 {
+            let noise = if i % 2 == 0 { 0.5_f32 } else { -0.5_f32 };
+            let value = 20_f32 + i as f32 + noise;
+            col.push(&PinState {pin: 3_u8, value: PinValue::Temperature(Temperature::new(value)), dt: now - Duration::seconds(90 - i * 10), until: None});
+        }
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now, until: None});
+
+        let naive = col.smoothed_temperature_rate(&since, 1).unwrap();
+        let smoothed = col.smoothed_temperature_rate(&since, 2).unwrap();
+        assert!((naive - 8_f32 / 90_f32).abs() < 0.0001);
+        assert!((smoothed - 0.1_f32).abs() < 0.0001);
+        assert!((smoothed - 0.1_f32).abs() < (naive - 0.1_f32).abs());
+
+        assert_eq!(col.smoothed_temperature_rate(&since, 0), None);
+        assert_eq!(col.smoothed_temperature_rate(&since, 6), None);
+    }
+
+    #[test]
+    fn test_pin_collection_on_duration()
+    {
+        let mut col = PinCollection::default();
+        let now = Local::now();
+        assert_eq!(col.on_duration(&(now - Duration::seconds(100)), &now), Duration::zero());
+
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now - Duration::seconds(50), until: None});
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(false), dt: now - Duration::seconds(30), until: None});
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now - Duration::seconds(20), until: None});
+
+        assert_eq!(col.on_duration(&(now - Duration::seconds(100)), &now), Duration::seconds(40));
+        // first transition predates start
+        assert_eq!(col.on_duration(&(now - Duration::seconds(40)), &now), Duration::seconds(30));
+        // still on at end
+        assert_eq!(col.on_duration(&(now - Duration::seconds(40)), &(now - Duration::seconds(10))), Duration::seconds(20));
+        assert_eq!(col.on_duration(&(now - Duration::seconds(30)), &(now - Duration::seconds(20))), Duration::zero());
+        assert_eq!(col.on_duration(&(now - Duration::seconds(200)), &(now - Duration::seconds(100))), Duration::zero());
+
+        // timed state stops counting once expired
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(false), dt: now - Duration::seconds(15), until: None});
+        col.push(&PinState {pin: 1_u8, value: PinValue::Digital(true), dt: now - Duration::seconds(10), until: Some(now - Duration::seconds(5))});
+        assert_eq!(col.on_duration(&(now - Duration::seconds(20)), &now), Duration::seconds(10));
     }
 }
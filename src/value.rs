@@ -0,0 +1,603 @@
+use alloc::format;
+use alloc::string::{String, ToString};
+use core::fmt;
+use core::ops::Sub;
+#[cfg(feature = "std")]
+use yaml_rust::{Yaml};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::helper::{abs_f32, analog_to_percent, invert_analog};
+
+#[derive(new, Default, Debug, PartialEq, PartialOrd, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct Temperature
+{
+    pub value: f32
+}
+
+impl Sub for Temperature {
+    type Output = Temperature;
+    fn sub(self, other: Temperature) -> Temperature {
+        Temperature { value: self.value - other.value }
+    }
+}
+
+/**
+ * decimals used when a temperature is serialized into an mqtt payload
+ */
+pub const TEMPERATURE_PAYLOAD_DECIMALS: u8 = 2;
+
+impl Temperature
+{
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(s: &str) -> Result<Temperature, String>
+    {
+        Ok(Temperature::new(s.parse::<f32>().map_err(|_| format!("unable to parse temperature {}", s))?))
+    }
+
+    #[cfg(feature = "std")]
+    pub fn from_yaml(yaml: &Yaml) -> Option<Temperature>
+    {
+        let value = yaml.as_f64()
+            .or_else(|| yaml.as_i64().map(|v| v as f64))
+            .or_else(|| yaml.as_str().and_then(|s| s.trim().parse::<f64>().ok()))?;
+        Some(Temperature { value: value as f32 })
+    }
+
+    pub fn abs(&self) -> Temperature
+    {
+        Temperature { value: abs_f32(self.value) }
+    }
+
+    /**
+     * a NaN side is ignored, as with f32::max
+     */
+    pub fn max(self, other: Temperature) -> Temperature
+    {
+        Temperature { value: self.value.max(other.value) }
+    }
+
+    /**
+     * a NaN side is ignored, as with f32::min
+     */
+    pub fn min(self, other: Temperature) -> Temperature
+    {
+        Temperature { value: self.value.min(other.value) }
+    }
+
+    /**
+     * NaN clamps to low, low wins when low is above high
+     */
+    pub fn clamp(self, low: Temperature, high: Temperature) -> Temperature
+    {
+        if self.value.is_nan() {
+            return low;
+        }
+        self.min(high).max(low)
+    }
+
+    /**
+     * fixed number of decimals so 20.3 does not go out as 20.299999, a rounded -0 is sent as 0
+     */
+    pub fn to_payload(&self, decimals: u8) -> String
+    {
+        let payload = format!("{:.*}", decimals as usize, self.value);
+        match payload.strip_prefix('-') {
+            Some(unsigned) if unsigned.chars().all(|c| c == '0' || c == '.') => unsigned.to_string(),
+            _ => payload
+        }
+    }
+
+    /**
+     * Q8.8 fixed point: representable range is -128.0 to 127.99609375 in steps of 1/256,
+     * values are rounded to the nearest step and saturate outside the range
+     */
+    pub fn to_fixed_q8_8(&self) -> i16
+    {
+        let fixed = self.value * 256_f32;
+        (if fixed < 0_f32 { fixed - 0.5_f32 } else { fixed + 0.5_f32 }) as i16
+    }
+
+    pub fn from_fixed_q8_8(fixed: i16) -> Temperature
+    {
+        Temperature::new(fixed as f32 / 256_f32)
+    }
+
+    pub fn checked_sub(&self, other: &Temperature, max_reasonable: f32) -> Option<Temperature>
+    {
+        let diff = self.value - other.value;
+        if abs_f32(diff) > max_reasonable {
+            return None;
+        }
+        Some(Temperature::new(diff))
+    }
+}
+
+pub const MAX_ANALOG: u16 = 1023;
+
+/**
+ * plausible readings for a sensor e.g. -50..=150 for most thermistors
+ */
+#[derive(new, Debug, PartialEq, Clone)]
+pub struct TemperatureRange
+{
+    pub min: f32,
+    pub max: f32
+}
+
+impl TemperatureRange
+{
+    pub fn contains(&self, temperature: &Temperature) -> bool
+    {
+        temperature.value >= self.min && temperature.value <= self.max
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum PinError
+{
+    InvalidTopic(&'static str),
+    InvalidValue(&'static str),
+    InvalidPin(u8),
+    OutOfRange(String),
+    UnknownKind(String)
+}
+
+impl fmt::Display for PinError
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result
+    {
+        match self {
+            PinError::InvalidTopic(reason) => write!(f, "invalid topic: {}", reason),
+            PinError::InvalidValue(reason) => write!(f, "invalid value: {}", reason),
+            PinError::InvalidPin(pin) => write!(f, "invalid pin {}", pin),
+            PinError::OutOfRange(reason) => write!(f, "value out of range: {}", reason),
+            PinError::UnknownKind(kind) => write!(f, "unknown pin value type {}", kind)
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PinError {}
+
+/**
+ * with serde it is written as the json body PinOperation::from_json_message reads e.g. {"type":"analog","value":512}
+ */
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(tag = "type", content = "value", rename_all = "lowercase"))]
+pub enum PinValue
+{
+    Temperature(Temperature),
+    Analog(u16),
+    Digital(bool),
+    Voltage(f32)
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PinCommand
+{
+    Relay(bool),
+    Pwm(u16),
+    NoOp
+}
+
+/**
+ * hashable form of PinValue for use as a map key, floats compare by bit pattern
+ */
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum PinValueKey
+{
+    Temperature(u32),
+    Analog(u16),
+    Digital(bool),
+    Voltage(u32)
+}
+
+impl From<&PinValue> for PinValueKey
+{
+    fn from(value: &PinValue) -> PinValueKey
+    {
+        match value {
+            PinValue::Temperature(v) => PinValueKey::Temperature(v.value.to_bits()),
+            PinValue::Analog(v) => PinValueKey::Analog(*v),
+            PinValue::Digital(v) => PinValueKey::Digital(*v),
+            PinValue::Voltage(v) => PinValueKey::Voltage(v.to_bits())
+        }
+    }
+}
+
+impl PinValue
+{
+    /**
+     * analog is mirrored within MAX_ANALOG, digital is negated, temperature and voltage are kept
+     */
+    pub fn inverted(&self) -> PinValue
+    {
+        match self {
+            PinValue::Analog(v) => PinValue::Analog(invert_analog(*v, MAX_ANALOG)),
+            PinValue::Digital(v) => PinValue::Digital(!*v),
+            value => value.clone()
+        }
+    }
+
+    /**
+     * sensor readings (temperature, voltage) are not commands
+     */
+    pub fn to_command(&self) -> PinCommand
+    {
+        match self {
+            PinValue::Digital(v) => PinCommand::Relay(*v),
+            PinValue::Analog(v) => PinCommand::Pwm((*v).min(MAX_ANALOG)),
+            PinValue::Temperature(_) | PinValue::Voltage(_) => PinCommand::NoOp
+        }
+    }
+
+    pub fn key(&self) -> PinValueKey
+    {
+        PinValueKey::from(self)
+    }
+
+    pub fn from_string(kind: &str, message: &str) -> Result<PinValue, PinError>
+    {
+        match kind {
+                "digital" => {
+                    let value = match message {
+                        m if m.eq_ignore_ascii_case("on") || m.eq_ignore_ascii_case("true") => true,
+                        m if m.eq_ignore_ascii_case("off") || m.eq_ignore_ascii_case("false") => false,
+                        m => m.parse::<u8>().map_err(|_| PinError::InvalidValue("Unable to parse digital value"))? > 0
+                    };
+                    Ok(PinValue::Digital(value))
+                },
+                "analog" => {
+                    let value = message.parse::<u16>().map_err(|_| PinError::InvalidValue("Unable to parse analog value"))?;
+                    Ok(PinValue::Analog(value))
+                },
+                "temperature" => {
+                    let value = message.parse::<f32>().map_err(|_| PinError::InvalidValue("Unable to parse temparature value"))?;
+                    Ok(PinValue::Temperature(Temperature {value }))
+                },
+                "voltage" => {
+                    let value = message.parse::<f32>().map_err(|_| PinError::InvalidValue("Unable to parse voltage value"))?;
+                    Ok(PinValue::Voltage(value))
+                }
+                _ => Err(PinError::UnknownKind(kind.to_string()))
+        }
+    }
+
+    /**
+     * same as from_string but temperatures outside range are rejected
+     */
+    pub fn from_string_bounded(kind: &str, message: &str, range: &TemperatureRange) -> Result<PinValue, PinError>
+    {
+        match PinValue::from_string(kind, message)? {
+            PinValue::Temperature(v) if !range.contains(&v) => Err(PinError::OutOfRange(format!("temperature {} outside {}..={}", v.value, range.min, range.max))),
+            value => Ok(value)
+        }
+    }
+
+    pub fn is_digital(&self) -> bool
+    {
+        matches!(self, PinValue::Digital(_))
+    }
+
+    pub fn is_analog(&self) -> bool
+    {
+        matches!(self, PinValue::Analog(_))
+    }
+
+    pub fn is_temperature(&self) -> bool
+    {
+        matches!(self, PinValue::Temperature(_))
+    }
+
+    pub fn is_voltage(&self) -> bool
+    {
+        matches!(self, PinValue::Voltage(_))
+    }
+
+    pub fn is_on(&self) -> bool
+    {
+        match self { PinValue::Analog(v) => *v > 0u16, PinValue::Digital(v) => *v, PinValue::Voltage(v) => *v > 0_f32, _ => false}
+    }
+
+    /**
+     * opt-in check for readings the hardware can not produce e.g. analog above MAX_ANALOG for 10-bit pins
+     */
+    pub fn validate(&self, max_analog: u16) -> Result<(), PinError>
+    {
+        match self {
+            PinValue::Analog(v) if *v > max_analog => Err(PinError::OutOfRange(format!("analog value {} above {}", v, max_analog))),
+            _ => Ok(())
+        }
+    }
+
+    pub fn kind(&self) -> &'static str
+    {
+        match self { PinValue::Temperature(_) => "temperature", PinValue::Analog(_) => "analog", PinValue::Digital(_) => "digital", PinValue::Voltage(_) => "voltage"}
+    }
+
+    pub fn to_payload(&self) -> String
+    {
+        match self { PinValue::Temperature(v) => v.to_payload(TEMPERATURE_PAYLOAD_DECIMALS), PinValue::Analog(v) => v.to_string(), PinValue::Digital(v) => (*v as u8).to_string(), PinValue::Voltage(v) => v.to_string()}
+    }
+
+    pub fn as_percent(&self) -> Option<u8>
+    {
+        match self { PinValue::Analog(v) => Some(analog_to_percent(*v)), PinValue::Digital(v) => Some(if *v { 100 } else { 0 }), _ => None}
+    }
+
+    /**
+     * analog within 0.0..=1.0 of max_analog, digital is 1.0 or 0.0, None for temperature, voltage or a max_analog of 0
+     */
+    pub fn as_ratio(&self, max_analog: u16) -> Option<f32>
+    {
+        match self {
+            PinValue::Analog(_) if max_analog == 0 => None,
+            PinValue::Analog(v) => Some((*v as f32 / max_analog as f32).min(1_f32)),
+            PinValue::Digital(v) => Some(if *v { 1_f32 } else { 0_f32 }),
+            _ => None
+        }
+    }
+
+    pub fn as_ratio_default(&self) -> Option<f32>
+    {
+        self.as_ratio(MAX_ANALOG)
+    }
+
+    /**
+     * digital is 1.0 or 0.0
+     */
+    pub fn as_f32(&self) -> f32
+    {
+        match self { PinValue::Temperature(v) => v.value, PinValue::Analog(v) => *v as f32, PinValue::Digital(v) => if *v { 1_f32 } else { 0_f32 }, PinValue::Voltage(v) => *v }
+    }
+
+    pub fn as_u16(&self) -> u16
+    {
+        match self { PinValue::Analog(v) => *v, PinValue::Digital(v) => *v as u16, _ => 0}
+    }
+}
+
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests
+{
+    use super::*;
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_temperature_from_yaml()
+    {
+        let docs = yaml_rust::YamlLoader::load_from_str("float: 20.5\ninteger: 20\nquoted: \"20.5\"\ntext: warm\nnegative: -3").unwrap();
+        let doc = &docs[0];
+        assert_eq!(Temperature::from_yaml(&doc["float"]), Some(Temperature::new(20.5_f32)));
+        assert_eq!(Temperature::from_yaml(&doc["integer"]), Some(Temperature::new(20_f32)));
+        assert_eq!(Temperature::from_yaml(&doc["quoted"]), Some(Temperature::new(20.5_f32)));
+        assert_eq!(Temperature::from_yaml(&doc["negative"]), Some(Temperature::new(-3_f32)));
+        assert_eq!(Temperature::from_yaml(&doc["text"]), None);
+        assert_eq!(Temperature::from_yaml(&doc["missing"]), None);
+    }
+
+    #[test]
+    fn test_temperature_checked_sub()
+    {
+        assert_eq!(Temperature::new(22_f32).checked_sub(&Temperature::new(20_f32), 10_f32), Some(Temperature::new(2_f32)));
+        assert_eq!(Temperature::new(-5_f32).checked_sub(&Temperature::new(3_f32), 10_f32), Some(Temperature::new(-8_f32)));
+        assert_eq!(Temperature::new(20_f32).checked_sub(&Temperature::new(30_f32), 10_f32), Some(Temperature::new(-10_f32)));
+        assert_eq!(Temperature::new(85_f32).checked_sub(&Temperature::new(20_f32), 10_f32), None);
+        assert_eq!(Temperature::new(-40_f32).checked_sub(&Temperature::new(20_f32), 10_f32), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_value_serde()
+    {
+        assert_eq!(serde_json::to_string(&Temperature::new(21.5_f32)).unwrap(), "21.5");
+        assert_eq!(serde_json::from_str::<Temperature>("21.5").unwrap(), Temperature::new(21.5_f32));
+        assert_eq!(serde_json::to_string(&PinValue::Analog(512)).unwrap(), r#"{"type":"analog","value":512}"#);
+        assert_eq!(serde_json::to_string(&PinValue::Temperature(Temperature::new(-4.5_f32))).unwrap(), r#"{"type":"temperature","value":-4.5}"#);
+        for value in &[PinValue::Temperature(Temperature::new(21.5_f32)), PinValue::Analog(512), PinValue::Digital(true), PinValue::Voltage(3.3_f32)] {
+            let json = serde_json::to_string(value).unwrap();
+            assert_eq!(&serde_json::from_str::<PinValue>(&json).unwrap(), value);
+        }
+        assert_eq!(serde_json::from_str::<PinValue>(r#"{"type":"pressure","value":1}"#).is_err(), true);
+    }
+
+    #[test]
+    fn test_temperature_min_max_clamp()
+    {
+        assert_eq!(Temperature::new(-5_f32) < Temperature::new(3_f32), true);
+        assert_eq!(Temperature::new(-5_f32).max(Temperature::new(3_f32)), Temperature::new(3_f32));
+        assert_eq!(Temperature::new(-5_f32).min(Temperature::new(3_f32)), Temperature::new(-5_f32));
+        assert_eq!(Temperature::new(f32::NAN).max(Temperature::new(3_f32)), Temperature::new(3_f32));
+        assert_eq!(Temperature::new(3_f32).min(Temperature::new(f32::NAN)), Temperature::new(3_f32));
+
+        let (low, high) = (Temperature::new(16_f32), Temperature::new(26_f32));
+        assert_eq!(Temperature::new(21.5_f32).clamp(low.clone(), high.clone()), Temperature::new(21.5_f32));
+        assert_eq!(Temperature::new(-3_f32).clamp(low.clone(), high.clone()), low);
+        assert_eq!(Temperature::new(40_f32).clamp(low.clone(), high.clone()), high);
+        assert_eq!(Temperature::new(f32::NAN).clamp(low.clone(), high.clone()), low);
+        assert_eq!(Temperature::new(20_f32).clamp(high.clone(), low.clone()), high);
+    }
+
+    #[test]
+    fn test_temperature_to_payload()
+    {
+        assert_eq!(Temperature::new(20.3_f32).to_payload(2), "20.30");
+        assert_eq!(Temperature::new(20.29999_f32).to_payload(1), "20.3");
+        assert_eq!(Temperature::new(-4.567_f32).to_payload(2), "-4.57");
+        assert_eq!(Temperature::new(-0.001_f32).to_payload(2), "0.00");
+        assert_eq!(Temperature::new(21.5_f32).to_payload(0), "22");
+        assert_eq!(PinValue::Temperature(Temperature::new(20.3_f32)).to_payload(), "20.30");
+
+        for value in &[20.3_f32, -4.567, 0.1] {
+            let payload = Temperature::new(*value).to_payload(TEMPERATURE_PAYLOAD_DECIMALS);
+            match PinValue::from_string("temperature", &payload).unwrap() {
+                PinValue::Temperature(t) => assert_eq!((t.value - value).abs() <= 0.005, true),
+                v => panic!("unexpected {:?}", v)
+            }
+        }
+    }
+
+    #[test]
+    fn test_temperature_fixed_q8_8()
+    {
+        assert_eq!(Temperature::new(0_f32).to_fixed_q8_8(), 0);
+        assert_eq!(Temperature::new(1_f32).to_fixed_q8_8(), 256);
+        assert_eq!(Temperature::new(-1.5_f32).to_fixed_q8_8(), -384);
+        assert_eq!(Temperature::new(200_f32).to_fixed_q8_8(), i16::MAX);
+        assert_eq!(Temperature::new(-200_f32).to_fixed_q8_8(), i16::MIN);
+        assert_eq!(Temperature::from_fixed_q8_8(i16::MAX), Temperature::new(128_f32 - 1_f32 / 256_f32));
+        assert_eq!(Temperature::from_fixed_q8_8(i16::MIN), Temperature::new(-128_f32));
+
+        for value in &[-128_f32, -40.25_f32, -0.5_f32, 0_f32, 21.5_f32, 36.6_f32, 85.125_f32, 127.99_f32] {
+            let restored = Temperature::from_fixed_q8_8(Temperature::new(*value).to_fixed_q8_8());
+            assert!((restored.value - value).abs() <= 1_f32 / 512_f32);
+        }
+    }
+
+    #[test]
+    fn test_pin_value_from_string_digital()
+    {
+        assert_eq!(PinValue::from_string("digital", "on"), Ok(PinValue::Digital(true)));
+        assert_eq!(PinValue::from_string("digital", "OFF"), Ok(PinValue::Digital(false)));
+        assert_eq!(PinValue::from_string("digital", "true"), Ok(PinValue::Digital(true)));
+        assert_eq!(PinValue::from_string("digital", "False"), Ok(PinValue::Digital(false)));
+        assert_eq!(PinValue::from_string("digital", "0"), Ok(PinValue::Digital(false)));
+        assert_eq!(PinValue::from_string("digital", "1"), Ok(PinValue::Digital(true)));
+        assert_eq!(PinValue::from_string("digital", "7"), Ok(PinValue::Digital(true)));
+        assert_eq!(PinValue::from_string("digital", "maybe"), Err(PinError::InvalidValue("Unable to parse digital value")));
+    }
+
+    #[test]
+    fn test_pin_value_inverted()
+    {
+        assert_eq!(PinValue::Analog(0).inverted(), PinValue::Analog(1023));
+        assert_eq!(PinValue::Analog(1023).inverted(), PinValue::Analog(0));
+        assert_eq!(PinValue::Analog(300).inverted(), PinValue::Analog(723));
+        assert_eq!(PinValue::Analog(5000).inverted(), PinValue::Analog(0));
+        assert_eq!(PinValue::Digital(true).inverted(), PinValue::Digital(false));
+        assert_eq!(PinValue::Digital(false).inverted(), PinValue::Digital(true));
+        assert_eq!(PinValue::Temperature(Temperature::new(-3_f32)).inverted(), PinValue::Temperature(Temperature::new(-3_f32)));
+        assert_eq!(PinValue::Voltage(3.3_f32).inverted(), PinValue::Voltage(3.3_f32));
+    }
+
+    #[test]
+    fn test_pin_value_to_command()
+    {
+        assert_eq!(PinValue::Digital(true).to_command(), PinCommand::Relay(true));
+        assert_eq!(PinValue::Digital(false).to_command(), PinCommand::Relay(false));
+        assert_eq!(PinValue::Analog(512).to_command(), PinCommand::Pwm(512));
+        assert_eq!(PinValue::Analog(1023).to_command(), PinCommand::Pwm(1023));
+        assert_eq!(PinValue::Analog(4000).to_command(), PinCommand::Pwm(1023));
+        assert_eq!(PinValue::Temperature(Temperature::new(20_f32)).to_command(), PinCommand::NoOp);
+        assert_eq!(PinValue::Voltage(3.3_f32).to_command(), PinCommand::NoOp);
+    }
+
+    #[test]
+    fn test_pin_value_key()
+    {
+        let mut counts = std::collections::HashMap::new();
+        let values = [PinValue::Digital(true), PinValue::Analog(300), PinValue::Digital(true), PinValue::Temperature(Temperature::new(20.5_f32)),
+            PinValue::Analog(300), PinValue::Digital(false), PinValue::Temperature(Temperature::new(20.5_f32)), PinValue::Analog(300)];
+        for value in values.iter() {
+            *counts.entry(value.key()).or_insert(0_usize) += 1;
+        }
+        assert_eq!(counts.len(), 4);
+        assert_eq!(counts[&PinValue::Digital(true).key()], 2);
+        assert_eq!(counts[&PinValue::Digital(false).key()], 1);
+        assert_eq!(counts[&PinValue::Analog(300).key()], 3);
+        assert_eq!(counts[&PinValue::Temperature(Temperature::new(20.5_f32)).key()], 2);
+        assert_eq!(PinValue::Analog(1).key() == PinValue::Voltage(1_f32).key(), false);
+    }
+
+    #[test]
+    fn test_pin_value_as_f32()
+    {
+        assert_eq!(PinValue::Temperature(Temperature::new(-7.25_f32)).as_f32(), -7.25_f32);
+        assert_eq!(PinValue::Temperature(Temperature::new(21.5_f32)).as_f32(), 21.5_f32);
+        assert_eq!(PinValue::Analog(512).as_f32(), 512_f32);
+        assert_eq!(PinValue::Digital(true).as_f32(), 1_f32);
+        assert_eq!(PinValue::Digital(false).as_f32(), 0_f32);
+        assert_eq!(PinValue::Voltage(3.3_f32).as_f32(), 3.3_f32);
+        assert_eq!(PinValue::Temperature(Temperature::new(21.5_f32)).as_u16(), 0);
+    }
+
+    #[test]
+    fn test_pin_value_from_string_bounded()
+    {
+        let range = TemperatureRange::new(-50_f32, 150_f32);
+        assert_eq!(PinValue::from_string_bounded("temperature", "-12.5", &range), Ok(PinValue::Temperature(Temperature::new(-12.5_f32))));
+        assert_eq!(PinValue::from_string_bounded("temperature", "150", &range), Ok(PinValue::Temperature(Temperature::new(150_f32))));
+        assert_eq!(PinValue::from_string_bounded("temperature", "-300", &range), Err(PinError::OutOfRange("temperature -300 outside -50..=150".to_string())));
+        assert_eq!(PinValue::from_string_bounded("temperature", "1e9", &range).is_err(), true);
+        assert_eq!(PinValue::from_string_bounded("temperature", "NaN", &range).is_err(), true);
+        assert_eq!(PinValue::from_string_bounded("analog", "1000", &range), Ok(PinValue::Analog(1000)));
+
+        assert_eq!(PinValue::from_string("temperature", "-300"), Ok(PinValue::Temperature(Temperature::new(-300_f32))));
+        assert_eq!(PinValue::from_string("temperature", "1e9"), Ok(PinValue::Temperature(Temperature::new(1e9_f32))));
+    }
+
+    #[test]
+    fn test_pin_value_voltage()
+    {
+        assert_eq!(PinValue::from_string("voltage", "3.30"), Ok(PinValue::Voltage(3.3_f32)));
+        assert_eq!(PinValue::from_string("voltage", "high"), Err(PinError::InvalidValue("Unable to parse voltage value")));
+        assert_eq!(PinValue::from_string("analog", "3.30"), Err(PinError::InvalidValue("Unable to parse analog value")));
+        assert_eq!(PinValue::from_string("analog", "330"), Ok(PinValue::Analog(330)));
+        assert_eq!(PinValue::Voltage(3.3_f32).is_on(), true);
+        assert_eq!(PinValue::Voltage(0_f32).is_on(), false);
+        assert_eq!(PinValue::Voltage(3.3_f32).is_voltage(), true);
+        assert_eq!(PinValue::Voltage(3.3_f32).is_analog(), false);
+        assert_eq!(PinValue::Voltage(3.3_f32).kind(), "voltage");
+        assert_eq!(PinValue::Voltage(3.3_f32).to_payload(), "3.3");
+        assert_eq!(PinValue::Voltage(3.3_f32).as_percent(), None);
+    }
+
+    #[test]
+    fn test_pin_value_validate()
+    {
+        assert_eq!(PinValue::Analog(0).validate(MAX_ANALOG), Ok(()));
+        assert_eq!(PinValue::Analog(1023).validate(MAX_ANALOG), Ok(()));
+        assert_eq!(PinValue::Analog(1024).validate(MAX_ANALOG), Err(PinError::OutOfRange("analog value 1024 above 1023".to_string())));
+        assert_eq!(PinValue::from_string("analog", "5000").unwrap().validate(MAX_ANALOG).is_err(), true);
+        assert_eq!(PinValue::Analog(4095).validate(4095), Ok(()));
+        assert_eq!(PinValue::Digital(true).validate(MAX_ANALOG), Ok(()));
+        assert_eq!(PinValue::Temperature(Temperature::new(2000_f32)).validate(MAX_ANALOG), Ok(()));
+    }
+
+    #[test]
+    fn test_pin_value_as_percent()
+    {
+        assert_eq!(PinValue::Analog(511).as_percent(), Some(50));
+        assert_eq!(PinValue::Analog(1023).as_percent(), Some(100));
+        assert_eq!(PinValue::Analog(0).as_percent(), Some(0));
+        assert_eq!(PinValue::Digital(true).as_percent(), Some(100));
+        assert_eq!(PinValue::Digital(false).as_percent(), Some(0));
+        assert_eq!(PinValue::Temperature(Temperature::new(20_f32)).as_percent(), None);
+    }
+
+    #[test]
+    fn test_pin_value_as_ratio()
+    {
+        assert_eq!(PinValue::Analog(1023).as_ratio_default(), Some(1_f32));
+        assert_eq!(PinValue::Analog(0).as_ratio_default(), Some(0_f32));
+        assert_eq!(PinValue::Analog(2000).as_ratio_default(), Some(1_f32));
+        assert_eq!(PinValue::Analog(128).as_ratio(256), Some(0.5_f32));
+        assert_eq!(PinValue::Analog(128).as_ratio(0), None);
+        assert_eq!(PinValue::Digital(true).as_ratio_default(), Some(1_f32));
+        assert_eq!(PinValue::Digital(false).as_ratio(0), Some(0_f32));
+        assert_eq!(PinValue::Temperature(Temperature::new(20_f32)).as_ratio_default(), None);
+        assert_eq!(PinValue::Voltage(3.3_f32).as_ratio_default(), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_pin_error_is_std_error()
+    {
+        let error: Box<dyn std::error::Error> = Box::new(PinError::UnknownKind("pressure".to_string()));
+        assert_eq!(error.to_string(), "unknown pin value type pressure");
+    }
+}